@@ -0,0 +1,183 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use tetris::*;
+use crate::simulator::*;
+use crate::util::{draw, f64_cmp};
+
+/// How a beam search over the coming pieces is run.
+#[derive(Clone, Copy, Debug)]
+pub struct Beam {
+	/// Number of states kept at each depth.
+	pub width: usize,
+	/// Number of pieces to place, counting the current one.
+	pub depth: usize,
+	/// Stop going deeper once this much time has gone by. `None` always
+	/// searches to the full depth.
+	pub budget: Option<Duration>
+}
+
+/// A state in the beam.
+struct Node {
+	state: State,
+	value: f64,
+	// Index of the first placement on the way here
+	root: usize,
+	// Position in the piece sequence of the piece to place next
+	piece: usize
+}
+
+/// Pick a piece out of `unseen` (chances in `MinoShape::ALL` order).
+fn sample(unseen: &[f64; 7]) -> MinoShape {
+	MinoShape::ALL[draw(unseen)]
+}
+
+/// Keep the `width` nodes the bot rates highest.
+fn narrow(mut nodes: Vec<Node>, width: usize) -> Vec<Node> {
+	nodes.sort_by(|a, b| f64_cmp(b.value, a.value));
+	nodes.truncate(width);
+
+	nodes
+}
+
+/// Choose between `placements` (every way to play the current piece from
+/// `state`) with a beam search, and return the index of the one chosen.
+///
+/// After the current piece, the search places `next` and then pieces drawn
+/// at random from `unseen` (see `turn`), keeping the `beam.width` states that
+/// `bot` rates highest at each depth. Duplicate states are only kept once.
+/// The placement chosen is the one leading to the best state at the deepest
+/// depth reached before running out of pieces, time, or places to put them.
+///
+/// Panics if `beam.width` is 0, since that leaves nothing to search.
+pub fn beam_search<T: Bot>(state: &State, placements: &[Placement], next: MinoShape, unseen: &[f64; 7], bot: &T, settings: &Settings, beam: &Beam) -> Option<usize> {
+	assert!(beam.width > 0, "beam width must be at least 1");
+	let start = Instant::now();
+
+	let mut sequence = vec![next];
+	while sequence.len() < beam.depth {
+		sequence.push(sample(unseen));
+	}
+
+	let roots = placements.iter().enumerate()
+		.map(|(root, placement)| Node {
+			state: placement.state,
+			value: bot.evaluate(&placement.state),
+			root,
+			// Holding into an empty slot uses up the next piece too
+			piece: if placement.held && state.hold.is_none() { 1 } else { 0 }
+		})
+		.collect();
+	let mut layer = narrow(roots, beam.width);
+
+	for _ in 1..beam.depth {
+		if beam.budget.is_some_and(|budget| start.elapsed() >= budget) {
+			break;
+		}
+
+		let mut seen = HashSet::new();
+		let mut children = Vec::new();
+		for node in layer.iter() {
+			let shape = match sequence.get(node.piece) {
+				Some(shape) => *shape,
+				None => sample(unseen)
+			};
+
+			for placement in candidates(&node.state, shape, settings) {
+				if seen.insert(placement.state) {
+					children.push(Node {
+						state: placement.state,
+						value: bot.evaluate(&placement.state),
+						root: node.root,
+						piece: node.piece + 1
+					});
+				}
+			}
+		}
+
+		// Every state in the beam tops out, so go with the last depth
+		if children.is_empty() {
+			break;
+		}
+		layer = narrow(children, beam.width);
+	}
+
+	layer.first().map(|node| node.root)
+}
+
+#[cfg(test)]
+mod beam_tests {
+	use tetris::*;
+	use crate::simulator::*;
+	use crate::beam::*;
+	use crate::test_util::*;
+
+	const WIDE: Beam = Beam { width: 1000, depth: 2, budget: None };
+
+	/// Rates states by the cells filled by each shape, times a weight per
+	/// shape (in `MinoShape::ALL` order).
+	struct Shapes([f64; 7]);
+
+	impl Bot for Shapes {
+		fn evaluate(&self, state: &State) -> f64 {
+			let mut value = 0.0;
+			for y in 0..20 {
+				for x in 0..10 {
+					if let Some(shape) = state.board.shape(x, y) {
+						value += self.0[shape.index()];
+					}
+				}
+			}
+			value
+		}
+	}
+
+	#[test]
+	fn chooses_root_of_best_deepest_state() {
+		// Bottom two rows filled, except for a 2x2 gap on the right
+		let mut state = State::new();
+		for y in 18..20 {
+			for x in 0..8 {
+				state.board.set(x, y, true);
+			}
+		}
+
+		let settings = Settings::default();
+		let placements = candidates(&state, MinoShape::I, &settings);
+		let best_after = |placement: &Placement| candidates(&placement.state, MinoShape::O, &settings).iter()
+			.map(|after| after.state.score)
+			.max()
+			.unwrap_or(0);
+
+		let i = beam_search(&state, &placements, MinoShape::O, &only(MinoShape::I), &Points, &settings, &WIDE).unwrap();
+		let best = placements.iter().map(best_after).max().unwrap();
+		assert_eq!(best_after(&placements[i]), best);
+	}
+
+	#[test]
+	fn holding_into_empty_slot_skips_next() {
+		// Holding the I plays the O now, so the T drawn after it comes next,
+		// rather than a second O
+		let state = State::new();
+		let settings = Settings { hold: true, ..Settings::default() };
+		let placements = state.hold_placements_with(MinoShape::I, MinoShape::O, |state, shape| candidates(state, shape, &settings));
+		let unseen = only(MinoShape::T);
+
+		let i = beam_search(&state, &placements, MinoShape::O, &unseen, &Shapes(only(MinoShape::T)), &settings, &WIDE).unwrap();
+		assert!(placements[i].held);
+
+		let mut o_not_t = only(MinoShape::O);
+		o_not_t[MinoShape::T.index()] = -1.0;
+		let i = beam_search(&state, &placements, MinoShape::O, &unseen, &Shapes(o_not_t), &settings, &WIDE).unwrap();
+		assert!(!placements[i].held);
+	}
+
+	#[test]
+	#[should_panic]
+	fn zero_width() {
+		let state = State::new();
+		let settings = Settings::default();
+		let placements = candidates(&state, MinoShape::T, &settings);
+		let beam = Beam { width: 0, ..WIDE };
+		beam_search(&state, &placements, MinoShape::O, &only(MinoShape::T), &Points, &settings, &beam);
+	}
+}
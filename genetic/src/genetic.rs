@@ -1,8 +1,5 @@
-#[path = "util.rs"]
-mod util;
-
 use std::thread::{JoinHandle, spawn};
-use util::f64_cmp;
+use crate::util::f64_cmp;
 
 /// Genetically evolvable individuals.
 /// 
@@ -25,7 +22,8 @@ pub trait Individual: std::marker::Send + 'static {
 /// Statistical summary of a population.
 #[derive(Debug)]
 pub struct Summary {
-	// 5-number summary of fitnesses
+	// 5-number summary of fitnesses (only read through `Debug`)
+	#[allow(dead_code)]
 	fitness_distribution: [f64; 5]
 }
 
@@ -0,0 +1,15 @@
+mod util;
+mod simulator;
+mod genetic;
+mod simple;
+mod beam;
+mod mcts;
+#[cfg(test)]
+mod test_util;
+
+pub use util::*;
+pub use simulator::*;
+pub use crate::genetic::*;
+pub use simple::*;
+pub use beam::*;
+pub use mcts::*;
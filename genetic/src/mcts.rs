@@ -0,0 +1,297 @@
+use std::time::{Duration, Instant};
+use tetris::*;
+use crate::simulator::*;
+use crate::util::{draw, f64_cmp};
+
+use rand::random;
+
+/// How long a Monte Carlo tree search gets to choose each placement. However
+/// small the budget, every placement gets tried at least once.
+#[derive(Clone, Copy, Debug)]
+pub enum Budget {
+	/// Run this many iterations.
+	Iterations(u32),
+	/// Run iterations until this much time has gone by.
+	Time(Duration)
+}
+
+/// A player that chooses placements by Monte Carlo tree search, rather than
+/// by a heuristic.
+///
+/// Each iteration walks down the tree, picking placements by UCT and drawing
+/// pieces according to the randomizer (the next piece is always the known
+/// one), then plays out `rollout_depth` more pieces, starting with the known
+/// next piece if the tree hasn't placed it yet. Playouts are worth the points
+/// they score, plus one for surviving to the end; topping out is worth
+/// nothing. The placement tried most often at the root wins, with ties going
+/// to the best average playout.
+///
+/// The search can't hold, so it panics if `settings.hold` is set.
+#[derive(Debug)]
+pub struct Mcts<B: Bot> {
+	/// How long to search for each placement.
+	pub budget: Budget,
+	/// Bot to place rollout pieces greedily with. `None` places them at
+	/// random.
+	pub rollout: Option<B>,
+	/// Number of pieces to play out past the tree.
+	pub rollout_depth: usize,
+	/// UCT exploration constant, applied to values scaled so that the best
+	/// playout so far is worth 1.
+	pub exploration: f64
+}
+
+/// A placement tried from a decision node.
+struct Edge {
+	state: State,
+	visits: u32,
+	total: f64,
+	// Chance node for the piece after this placement, once it's expanded
+	chance: Option<usize>
+}
+
+/// A point in the tree where a piece is about to be placed.
+struct Decision {
+	edges: Vec<Edge>,
+	visits: u32
+}
+
+/// A point in the tree where the next piece is drawn.
+struct Chance {
+	state: State,
+	// Chance of each piece, in `MinoShape::ALL` order
+	odds: [f64; 7],
+	// Decision node for each piece, once it's been drawn
+	children: [Option<usize>; 7]
+}
+
+impl<B: Bot> Mcts<B> {
+	/// Construct a search with random rollouts of `rollout_depth` pieces and
+	/// the usual exploration constant of sqrt(2).
+	pub fn new(budget: Budget, rollout_depth: usize) -> Mcts<B> {
+		Mcts {
+			budget,
+			rollout: None,
+			rollout_depth,
+			exploration: 2f64.sqrt()
+		}
+	}
+
+	/// Make a decision node for choosing between `placements`.
+	fn decision(placements: &[Placement]) -> Decision {
+		Decision {
+			edges: placements.iter()
+				.map(|placement| Edge {
+					state: placement.state,
+					visits: 0,
+					total: 0.0,
+					chance: None
+				})
+				.collect(),
+			visits: 0
+		}
+	}
+
+	/// Choose an edge out of `decision` by UCT, trying every edge once first.
+	fn select(&self, decision: &Decision, scale: f64) -> usize {
+		if let Some(i) = decision.edges.iter().position(|edge| edge.visits == 0) {
+			return i;
+		}
+
+		let log = (decision.visits as f64).ln();
+		let uct = |edge: &Edge| {
+			let mean = edge.total / edge.visits as f64 / scale;
+			mean + self.exploration * (log / edge.visits as f64).sqrt()
+		};
+
+		(0..decision.edges.len())
+			.max_by(|a, b| f64_cmp(uct(&decision.edges[*a]), uct(&decision.edges[*b])))
+			.unwrap()
+	}
+
+	/// Play out from `state`, starting with `first` if it's set, and return
+	/// the state the playout ends in, or `None` if it tops out.
+	fn rollout(&self, state: &State, first: Option<MinoShape>, unseen: &[f64; 7], settings: &Settings) -> Option<State> {
+		let mut state = *state;
+		for i in 0..self.rollout_depth {
+			let shape = match first {
+				Some(shape) if i == 0 => shape,
+				_ => MinoShape::ALL[draw(unseen)]
+			};
+			let placements = candidates(&state, shape, settings);
+			if placements.is_empty() {
+				return None;
+			}
+
+			state = match &self.rollout {
+				Some(bot) => placements.iter()
+					.map(|placement| placement.state)
+					.max_by(|a, b| f64_cmp(bot.evaluate(a), bot.evaluate(b)))
+					.unwrap(),
+				None => placements[random::<usize>() % placements.len()].state
+			};
+		}
+
+		Some(state)
+	}
+}
+
+impl<B: Bot> Decider for Mcts<B> {
+	fn decide(&self, state: &State, current: MinoShape, next: MinoShape, unseen: &[f64; 7], settings: &Settings) -> Option<Placement> {
+		assert!(!settings.hold, "Monte Carlo tree search can't hold");
+
+		let mut placements = candidates(state, current, settings);
+		if placements.is_empty() {
+			return None;
+		}
+
+		let mut decisions = vec![Mcts::<B>::decision(&placements)];
+		let mut chances: Vec<Chance> = Vec::new();
+
+		// Best playout so far, for keeping values around 0 to 1
+		let mut scale = 1.0;
+
+		let start = Instant::now();
+		let mut iterations = 0;
+		while decisions[0].edges.iter().any(|edge| edge.visits == 0) || match self.budget {
+			Budget::Iterations(n) => iterations < n,
+			Budget::Time(budget) => start.elapsed() < budget
+		} {
+			iterations += 1;
+
+			// Selection and expansion, down to an edge that hasn't been tried
+			// (along with the piece after it, if that's the known next piece)
+			// or a decision with nowhere to place the piece
+			let mut path = Vec::new();
+			let mut d = 0;
+			let leaf = loop {
+				if decisions[d].edges.is_empty() {
+					break None;
+				}
+
+				let e = self.select(&decisions[d], scale);
+				path.push((d, e));
+
+				let edge = &decisions[d].edges[e];
+				if edge.visits == 0 {
+					break Some((edge.state, if d == 0 { Some(next) } else { None }));
+				}
+
+				let c = match edge.chance {
+					Some(c) => c,
+					None => {
+						// The piece after the root's is the known next piece
+						let mut odds = *unseen;
+						if d == 0 {
+							odds = [0.0; 7];
+							odds[next.index()] = 1.0;
+						}
+
+						chances.push(Chance {
+							state: edge.state,
+							odds,
+							children: [None; 7]
+						});
+						decisions[d].edges[e].chance = Some(chances.len() - 1);
+						chances.len() - 1
+					}
+				};
+
+				let i = draw(&chances[c].odds);
+				d = match chances[c].children[i] {
+					Some(d) => d,
+					None => {
+						let placements = candidates(&chances[c].state, MinoShape::ALL[i], settings);
+						decisions.push(Mcts::<B>::decision(&placements));
+						chances[c].children[i] = Some(decisions.len() - 1);
+						decisions.len() - 1
+					}
+				};
+			};
+
+			// Simulation
+			let value = match leaf.and_then(|(leaf, first)| self.rollout(&leaf, first, unseen, settings)) {
+				Some(end) => (end.score - state.score) as f64 + 1.0,
+				None => 0.0
+			};
+			if value > scale {
+				scale = value;
+			}
+
+			// Backpropagation
+			for (d, e) in path {
+				decisions[d].visits += 1;
+				decisions[d].edges[e].visits += 1;
+				decisions[d].edges[e].total += value;
+			}
+		}
+
+		let root = &decisions[0];
+		let mean = |edge: &Edge| edge.total / edge.visits as f64;
+		let best = (0..root.edges.len())
+			.max_by(|a, b| {
+				let (a, b) = (&root.edges[*a], &root.edges[*b]);
+				a.visits.cmp(&b.visits).then(f64_cmp(mean(a), mean(b)))
+			})
+			.unwrap();
+
+		Some(placements.swap_remove(best))
+	}
+}
+
+#[cfg(test)]
+mod mcts_tests {
+	use tetris::*;
+	use crate::simulator::*;
+	use crate::mcts::*;
+	use crate::test_util::*;
+
+	/// Get a state stacked up to the second row, except for a well on the
+	/// left. Only an I dropped down the well makes room for an O to spawn.
+	fn stacked() -> State {
+		let mut state = State::new();
+		for y in 2..20 {
+			for x in 1..10 {
+				state.board.set(x, y, true);
+			}
+		}
+		for x in 3..10 {
+			state.board.set(x, 1, true);
+		}
+		state
+	}
+
+	#[test]
+	fn finds_the_only_way_out() {
+		let state = stacked();
+		let unseen = only(MinoShape::O);
+		for n in [0, 5, 50].iter() {
+			let mcts: Mcts<Points> = Mcts::new(Budget::Iterations(*n), 2);
+			let placement = mcts.decide(&state, MinoShape::I, MinoShape::O, &unseen, &Settings::default()).unwrap();
+			assert_eq!(placement.clear(&state).lines, 4);
+		}
+	}
+
+	#[test]
+	fn rollouts_start_with_next() {
+		// Standing an I up out of the well leaves room for another I to spawn,
+		// but not an O
+		let state = stacked();
+		let settings = Settings::default();
+		let blocked = candidates(&state, MinoShape::I, &settings).into_iter()
+			.find(|placement| placement.mino.points()[0].1 == -2 && placement.clear(&state).lines == 0)
+			.unwrap();
+
+		let mcts = Mcts { rollout: Some(Points), ..Mcts::new(Budget::Iterations(0), 1) };
+		assert!(mcts.rollout(&blocked.state, None, &only(MinoShape::I), &settings).is_some());
+		assert!(mcts.rollout(&blocked.state, Some(MinoShape::O), &only(MinoShape::I), &settings).is_none());
+	}
+
+	#[test]
+	#[should_panic]
+	fn rejects_hold() {
+		let settings = Settings { hold: true, ..Settings::default() };
+		let mcts: Mcts<Points> = Mcts::new(Budget::Iterations(10), 2);
+		mcts.decide(&State::new(), MinoShape::T, MinoShape::O, &only(MinoShape::T), &settings);
+	}
+}
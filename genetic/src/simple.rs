@@ -0,0 +1,160 @@
+use tetris::*;
+use crate::simulator::*;
+use crate::beam::*;
+use crate::genetic::*;
+
+use rand::random;
+
+/// What bots are evolved to do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Goal {
+	/// Score as many points as possible, playing alone.
+	ScoreAttack,
+	/// Win versus games against a fixed opponent (see `Simple::sparring_partner`).
+	Battle
+}
+
+/// A very simple bot that takes four heuristics into account:
+///     1. Score
+///     2. Max board height
+///     3. Holiness (amount of empty space below a block)
+///     4. Board flatness
+///
+/// Bots play greedily while they're evolved, unless given a `beam` to search
+/// with.
+#[derive(Debug)]
+pub struct Simple {
+	pub weights: [f64; 4],
+	pub goal: Goal,
+	pub beam: Option<Beam>
+}
+
+impl Simple {
+	/// Make a bot with random weights, to be evolved for `goal` while playing
+	/// with `beam`.
+	pub fn new(goal: Goal, beam: Option<Beam>) -> Simple {
+		// Random genes are numbers between -100 and 100
+		// (only multiplied by 100 to make analyzing easier)
+		let gene = || (random::<f64>() - 0.5) * 200.0;
+
+		Simple {
+			weights: [
+				gene(),
+				gene(),
+				gene(),
+				gene()
+			],
+			goal,
+			beam
+		}
+	}
+
+	/// Hand-tuned bot that just avoids holes and bumps, for battle bots to
+	/// train against.
+	pub fn sparring_partner() -> Simple {
+		Simple {
+			weights: [0.0, 0.0, -100.0, -10.0],
+			goal: Goal::Battle,
+			beam: None
+		}
+	}
+
+	// Helper for `crossover`
+	fn from_mask(p1: &Simple, p2: &Simple, mask: u64) -> Simple {
+		let weight = |i| if mask & (1u64<<i) > 0 { p1.weights[i] } else { p2.weights[i] };
+		Simple {
+			weights: [
+				weight(0),
+				weight(1),
+				weight(2),
+				weight(3)
+			],
+			goal: p1.goal,
+			beam: p1.beam
+		}
+	}
+
+	/// Get the settings games are played with while evolving: sticking to
+	/// moves a DAS player could make, and searching with `beam` if it's set.
+	fn settings(&self) -> Settings {
+		Settings {
+			shifting: Some(Shifting::Das),
+			hold: false,
+			lookahead: false,
+			expectimax: None,
+			beam: self.beam
+		}
+	}
+}
+
+impl Bot for Simple {
+	fn evaluate(&self, state: &State) -> f64 {
+		let score = state.score as f64;
+
+		let max_height = state.board.heights().iter()
+			.copied()
+			.max().unwrap() as f64;
+		let holiness = state.board.holes() as f64;
+		let flatness = state.board.bumpiness() as f64;
+
+		let values = [score, max_height, holiness, flatness];
+		values.iter().zip(self.weights.iter())
+			.fold(0.0, |a, (v, w)| a + (v*w))
+	}
+}
+
+impl Individual for Simple {
+	fn fitness(&self) -> f64 {
+		// Simulate a few games to get a somewhat-accurate idea of how well
+		// this bot performs, with pieces dealt the way the cartridge deals
+		// them (from a random point in the LFSR's cycle)
+		let randomizer = || NesRandomizer::new(random::<u16>() | 1);
+		let settings = self.settings();
+		match self.goal {
+			Goal::ScoreAttack => simulate(5, self, randomizer, &settings),
+			Goal::Battle => versus(5, self, &Simple::sparring_partner(), randomizer, &settings)
+		}
+	}
+
+	// Assign genes (weights) according to opposite non-zero bitmasks
+	fn crossover(p1: &Simple, p2: &Simple) -> (Simple, Simple) {
+		let mut mask = 0;
+		while mask != 0b0000 && mask != 0b1111 {
+			mask = random::<u64>() % (1<<4);
+		}
+
+		(Simple::from_mask(p1, p2, mask), Simple::from_mask(p2, p1, mask))
+	}
+
+	// Randomly mutate one gene by up to 10%.
+	fn mutate(self) -> Simple {
+		let mut mutated = self;
+
+		let i = random::<usize>() % 3;
+		let p = (random::<f64>() - 0.5) * 20.0;
+		mutated.weights[i] += mutated.weights[i] * p;
+		
+		mutated
+	}
+}
+
+#[cfg(test)]
+mod simple_tests {
+	use std::time::Duration;
+	use crate::*;
+
+	#[test]
+	fn beam_carries_through() {
+		let beam = Beam { width: 4, depth: 2, budget: Some(Duration::from_millis(10)) };
+		let p1 = Simple::new(Goal::ScoreAttack, Some(beam));
+		let p2 = Simple::new(Goal::ScoreAttack, Some(beam));
+
+		let (c1, c2) = Simple::crossover(&p1, &p2);
+		for bot in [p1, c1, c2.mutate()].iter() {
+			let settings = bot.settings().beam.unwrap();
+			assert_eq!((settings.width, settings.depth, settings.budget), (4, 2, beam.budget));
+		}
+
+		assert!(Simple::new(Goal::ScoreAttack, None).settings().beam.is_none());
+	}
+}
@@ -1,9 +1,8 @@
-#[path = "util.rs"]
-mod util;
-
 use tetris::*;
+use crate::beam::*;
+use crate::util::f64_cmp;
+
 use rand::random;
-use util::f64_cmp;
 
 /// A simulatable Tetris bot.
 pub trait Bot {
@@ -12,49 +11,496 @@ pub trait Bot {
 	fn evaluate(&self, state: &State) -> f64;
 }
 
-fn random_mino() -> MinoShape {
-	// Not uniformly distributed but it's definitely close enough
-    match (random::<u64>()) % 7 {
-        0 => MinoShape::I,
-        1 => MinoShape::J,
-        2 => MinoShape::L,
-        3 => MinoShape::O,
-        4 => MinoShape::S,
-        5 => MinoShape::T,
-        6 => MinoShape::Z,
-        _ => MinoShape::O
+/// Anything that can choose placements in a simulated game. Every `Bot` does,
+/// by playing each turn with `turn`.
+pub trait Decider {
+	/// Choose where to put `current`, with `next` coming after it and
+	/// `unseen` giving the chance of each piece after that (see `turn`).
+	/// Returns `None` if there's nowhere to put it. Not every decider can play
+	/// with every setting (`Mcts` can't hold).
+	fn decide(&self, state: &State, current: MinoShape, next: MinoShape, unseen: &[f64; 7], settings: &Settings) -> Option<Placement>;
+}
+
+impl<T: Bot> Decider for T {
+	fn decide(&self, state: &State, current: MinoShape, next: MinoShape, unseen: &[f64; 7], settings: &Settings) -> Option<Placement> {
+		turn(state, current, next, unseen, self, settings)
+	}
+}
+
+/// How simulated games are played.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Settings {
+    /// Restrict the bot to placements that a player shifting this way could
+    /// reach in time (see `State::reachable`). `None` allows any placement.
+    pub shifting: Option<Shifting>,
+    /// Let the bot use a hold slot. `Mcts` can't, and panics if asked to.
+    pub hold: bool,
+    /// Look ahead to the next piece: judge each placement by the best state
+    /// reachable after also placing the next piece (see `turn`).
+    pub lookahead: bool,
+    /// Search past the pieces the bot can see, over the pieces that might
+    /// come after them (see `turn`). `None` stops at the known pieces.
+    pub expectimax: Option<Expectimax>,
+    /// Choose placements with a beam search instead (see `beam_search`),
+    /// which replaces lookahead and expectimax. `None` plays greedily.
+    pub beam: Option<Beam>
+}
+
+/// How far an expectimax search looks into unseen pieces, and how much of
+/// each ply it explores.
+#[derive(Clone, Copy, Debug)]
+pub struct Expectimax {
+    /// Number of unseen pieces to search over, past `next`.
+    pub depth: usize,
+    /// Only search on from the `beam` placements the bot likes best on their
+    /// own at each ply. Must be at least 1.
+    pub beam: usize
+}
+
+/// Get every placement of `shape` allowed by `settings`.
+pub(crate) fn candidates(state: &State, shape: MinoShape, settings: &Settings) -> Vec<Placement> {
+    match settings.shifting {
+        Some(shifting) => state.reachable(shape, shifting, &NesScoring),
+        None => state.placements(shape, &NesRotation, &NesScoring)
+    }
+}
+
+/// Keep the placements worth searching further, according to the expectimax
+/// beam in `settings` (or all of them, without expectimax). Panics if the beam
+/// is 0, since that leaves nothing to search.
+fn prune<T: Bot>(placements: Vec<Placement>, bot: &T, settings: &Settings) -> Vec<Placement> {
+    let beam = match settings.expectimax {
+        Some(expectimax) => expectimax.beam,
+        None => { return placements; }
+    };
+    assert!(beam > 0, "expectimax beam must be at least 1");
+
+    let mut rated: Vec<(f64, Placement)> = placements.into_iter()
+        .map(|placement| (bot.evaluate(&placement.state), placement))
+        .collect();
+    rated.sort_by(|a, b| f64_cmp(b.0, a.0));
+    rated.truncate(beam);
+
+    rated.into_iter().map(|(_, placement)| placement).collect()
+}
+
+/// Get the best value the bot can get out of `placements`, searching `depth`
+/// unseen pieces past each. With no placements at all, the game is over, and
+/// that's the worst possible value.
+fn best<T: Bot>(placements: Vec<Placement>, bot: &T, settings: &Settings, unseen: &[f64; 7], depth: usize) -> f64 {
+    prune(placements, bot, settings).iter()
+        .map(|placement| expectimax(&placement.state, bot, settings, unseen, depth))
+        .fold(f64::NEG_INFINITY, f64::max)
+}
+
+/// Get the value of `state` to the bot, averaging over the next `depth`
+/// pieces. Each piece is weighted by its chance in `unseen`, and is placed
+/// wherever is best for the rest of the search.
+fn expectimax<T: Bot>(state: &State, bot: &T, settings: &Settings, unseen: &[f64; 7], depth: usize) -> f64 {
+    if depth == 0 {
+        return bot.evaluate(state);
     }
+
+    MinoShape::ALL.iter().zip(unseen.iter())
+        .filter(|(_, probability)| **probability > 0.0)
+        .map(|(shape, probability)| {
+            let placements = candidates(state, *shape, settings);
+            probability * best(placements, bot, settings, unseen, depth - 1)
+        })
+        .sum()
 }
 
 /// Run a single turn in the game. Finds and feeds possible future states to
-/// the bot, and returns the one that evaluates highest + the mino placed that
-/// got it there.
-fn turn<T: Bot>(state: &State, next: MinoShape, bot: &T) -> Option<(State, Mino)> {
-    let possibilities = state.possibilities(next);
+/// the bot, and returns the placement that evaluates highest.
+/// 
+/// `current` is the piece to place and `next` is the one after it. With hold
+/// enabled, the bot also considers holding `current` (see `State::hold`).
+///
+/// With lookahead enabled, each placement is scored by the best state the bot
+/// can get to by placing `next` afterwards (without holding), and placements
+/// that leave nowhere for `next` score lowest. Holding into an empty slot uses
+/// up `next`, so those placements are scored as they are.
+///
+/// With expectimax enabled, `next` is always placed as with lookahead, and
+/// states are then scored by searching on over `depth` unseen pieces, with
+/// `unseen` giving the chance of each (in `MinoShape::ALL` order) coming up
+/// after `next` (see `Randomizer::probabilities`).
+///
+/// With a beam set, the placement is chosen by `beam_search` instead.
+pub fn turn<T: Bot>(state: &State, current: MinoShape, next: MinoShape, unseen: &[f64; 7], bot: &T, settings: &Settings) -> Option<Placement> {
+    let mut placements = if settings.hold {
+        state.hold_placements_with(current, next, |state, shape| candidates(state, shape, settings))
+    }
+    else {
+        candidates(state, current, settings)
+    };
+
+    if let Some(beam) = settings.beam {
+        let i = beam_search(state, &placements, next, unseen, bot, settings, &beam)?;
+        return Some(placements.swap_remove(i));
+    }
+
+    let depth = settings.expectimax.map_or(0, |expectimax| expectimax.depth);
+    let value = |placement: &Placement| {
+        if (!settings.lookahead && settings.expectimax.is_none()) || (placement.held && state.hold.is_none()) {
+            return expectimax(&placement.state, bot, settings, unseen, depth);
+        }
+
+        let after = candidates(&placement.state, next, settings);
+        best(after, bot, settings, unseen, depth)
+    };
+
+    prune(placements, bot, settings).into_iter()
+        .map(|placement| (value(&placement), placement))
+        .max_by(|a, b| f64_cmp(a.0, b.0))
+        .map(|(_, placement)| placement)
+}
 
-    possibilities.into_iter().max_by(|(a, _): &(State, Mino), (b, _): &(State, Mino)| {
-        f64_cmp(bot.evaluate(a), bot.evaluate(b))
-    })
+/// One player's side of a game in progress.
+struct Player<R: Randomizer> {
+    state: State,
+    randomizer: R,
+    current: MinoShape,
+    next: MinoShape,
+    // Rows of garbage waiting to be pushed in
+    pending: i32
+}
+
+impl<R: Randomizer> Player<R> {
+    fn new(mut randomizer: R) -> Player<R> {
+        let current = randomizer.next();
+        let next = randomizer.next();
+
+        Player {
+            state: State::new(),
+            randomizer,
+            current,
+            next,
+            pending: 0
+        }
+    }
+
+    /// Have `bot` make the next placement, and return the number of garbage
+    /// rows it sends. Returns how the game ended instead if it's over.
+    ///
+    /// Garbage sent cancels out pending garbage first, and whatever is still
+    /// pending comes in (with holes from `holes`) after a placement that
+    /// doesn't clear any lines.
+    fn play<T: Decider>(&mut self, bot: &T, settings: &Settings, holes: &mut GarbageRandomizer) -> Result<i32, GameOutcome> {
+        // Games end by topping out, at the kill screen, or at 300 lines
+        if let Some(outcome) = self.state.outcome(self.current, &NesRotation, Some(300)) {
+            return Err(outcome);
+        }
+
+        let unseen = self.randomizer.probabilities();
+        let placement = match bot.decide(&self.state, self.current, self.next, &unseen, settings) {
+            Some(placement) => placement,
+            None => { return Err(GameOutcome::ToppedOut); }
+        };
+
+        // Roughly the frames spent falling, plus entry delay
+        let frames = (placement.mino.y() + 1) * gravity(self.state.level) + 10;
+        self.randomizer.elapse(frames as u32);
+
+        // Holding into an empty slot uses up the next piece too
+        if placement.held && self.state.hold.is_none() {
+            self.next = self.randomizer.next();
+        }
+        self.current = self.next;
+        self.next = self.randomizer.next();
+
+        let clear = placement.clear(&self.state);
+        let sent = garbage_sent(&self.state, &clear);
+        self.state = placement.state;
+
+        let cancelled = sent.min(self.pending);
+        self.pending -= cancelled;
+        if clear.lines == 0 && self.pending > 0 {
+            let hole = holes.hole(self.state.board.width());
+            self.state = match self.state.add_garbage(self.pending as usize, hole) {
+                Some(state) => state,
+                None => { return Err(GameOutcome::ToppedOut); }
+            };
+            self.pending = 0;
+        }
+
+        Ok(sent - cancelled)
+    }
 }
 
 /// Simulate `n` games played by `bot` and return the average score.
-pub fn simulate<T: Bot>(n: u32, bot: &T) -> f64 {
+/// 
+/// Each game draws its pieces from a fresh randomizer made by `randomizer`,
+/// and is played according to `settings`.
+pub fn simulate<T, R, F>(n: u32, bot: &T, randomizer: F, settings: &Settings) -> f64
+    where T: Decider, R: Randomizer, F: Fn() -> R
+{
     let mut sum = 0.0;
 
     for _ in 0..n {
-		let mut state = State::new();
-        // Simulated kill-screen at 300 lines
-        // MARK: Not in line with real NES Tetris
-        while state.lines < 300 {
-            let next = random_mino();
-            state = match turn(&state, next, bot) {
-                Some((state, _)) => state,
-                None => { break; }
-            };
-        }
+        let mut player = Player::new(randomizer());
+        // Nothing sends garbage in a solo game
+        let mut holes = GarbageRandomizer::new(0);
+        while player.play(bot, settings, &mut holes).is_ok() {}
 
-        sum += state.score as f64;
+        sum += player.state.score as f64;
     }
 
     sum / (n as f64)
-}
\ No newline at end of file
+}
+
+/// Simulate `n` versus games between `a` and `b` and return the fraction of
+/// them that `a` wins, counting draws as half a win.
+/// 
+/// The bots take turns placing pieces, each drawing from its own fresh
+/// randomizer made by `randomizer`, and lines they clear send garbage to the
+/// other (see `garbage_sent`). A bot loses by topping out; games that end any
+/// other way (see `State::outcome`) are draws.
+pub fn versus<A, B, R, F>(n: u32, a: &A, b: &B, randomizer: F, settings: &Settings) -> f64
+    where A: Decider, B: Decider, R: Randomizer, F: Fn() -> R
+{
+    let mut wins = 0.0;
+
+    for _ in 0..n {
+        let mut holes = GarbageRandomizer::new(random());
+        let mut player_a = Player::new(randomizer());
+        let mut player_b = Player::new(randomizer());
+
+        wins += loop {
+            match player_a.play(a, settings, &mut holes) {
+                Ok(sent) => { player_b.pending += sent; },
+                Err(GameOutcome::ToppedOut) => { break 0.0; },
+                Err(_) => { break 0.5; }
+            }
+            match player_b.play(b, settings, &mut holes) {
+                Ok(sent) => { player_a.pending += sent; },
+                Err(GameOutcome::ToppedOut) => { break 1.0; },
+                Err(_) => { break 0.5; }
+            }
+        };
+    }
+
+    wins / (n as f64)
+}
+
+#[cfg(test)]
+mod simulator_tests {
+    use std::cell::Cell;
+    use tetris::*;
+    use crate::simulator::*;
+    use crate::test_util::*;
+
+    const UNIFORM: [f64; 7] = [1.0 / 7.0; 7];
+
+    /// Rates states by the number of filled cells, times a weight.
+    struct Cells(f64);
+
+    impl Bot for Cells {
+        fn evaluate(&self, state: &State) -> f64 {
+            let cells: u32 = (0..20).map(|y| state.board.row_mask(y).count_ones()).sum();
+            self.0 * cells as f64
+        }
+    }
+
+    /// Never finds anywhere to put a piece.
+    struct Quitter;
+
+    impl Decider for Quitter {
+        fn decide(&self, _: &State, _: MinoShape, _: MinoShape, _: &[f64; 7], _: &Settings) -> Option<Placement> {
+            None
+        }
+    }
+
+    /// Holds whenever it can.
+    struct Holder;
+
+    impl Decider for Holder {
+        fn decide(&self, state: &State, current: MinoShape, next: MinoShape, _: &[f64; 7], settings: &Settings) -> Option<Placement> {
+            state.hold_placements_with(current, next, |state, shape| candidates(state, shape, settings))
+                .into_iter()
+                .find(|placement| placement.held)
+        }
+    }
+
+    /// Counts the states another bot evaluates.
+    struct Counted<T: Bot> {
+        bot: T,
+        count: Cell<usize>
+    }
+
+    impl<T: Bot> Bot for Counted<T> {
+        fn evaluate(&self, state: &State) -> f64 {
+            self.count.set(self.count.get() + 1);
+            self.bot.evaluate(state)
+        }
+    }
+
+    /// Get a state with the bottom two rows filled, except for a 2x2 gap on
+    /// the right that an O fits into.
+    fn o_gap() -> State {
+        let mut state = State::new();
+        for y in 18..20 {
+            for x in 0..8 {
+                state.board.set(x, y, true);
+            }
+        }
+        state
+    }
+
+    /// Get the most lines an O can clear from `state`.
+    fn o_clears(state: &State) -> i32 {
+        candidates(state, MinoShape::O, &Settings::default()).iter()
+            .map(|placement| placement.clear(state).lines)
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn lookahead_leaves_room_for_next() {
+        let state = o_gap();
+
+        // Dropping the I into the gap earns the most push down points on its
+        // own, but blocks the O from clearing both rows
+        let greedy = turn(&state, MinoShape::I, MinoShape::O, &UNIFORM, &Points, &Settings::default()).unwrap();
+        assert_eq!(o_clears(&greedy.state), 0);
+
+        let settings = Settings { lookahead: true, ..Settings::default() };
+        let ahead = turn(&state, MinoShape::I, MinoShape::O, &UNIFORM, &Points, &settings).unwrap();
+        assert_eq!(o_clears(&ahead.state), 2);
+    }
+
+    #[test]
+    fn lookahead_after_holding_into_empty_slot() {
+        // Holding the I plays the O now, and leaves nothing to look ahead to,
+        // so it only ever places one piece
+        let state = State::new();
+        let settings = Settings { hold: true, lookahead: true, ..Settings::default() };
+
+        let more = turn(&state, MinoShape::I, MinoShape::O, &UNIFORM, &Cells(1.0), &settings).unwrap();
+        assert!(!more.held);
+
+        let fewer = turn(&state, MinoShape::I, MinoShape::O, &UNIFORM, &Cells(-1.0), &settings).unwrap();
+        assert!(fewer.held);
+    }
+
+    #[test]
+    fn expectimax_weighs_unseen_pieces() {
+        let state = o_gap();
+        let settings = Settings {
+            expectimax: Some(Expectimax { depth: 1, beam: 1000 }),
+            ..Settings::default()
+        };
+        let choose = |unseen: &[f64; 7]| turn(&state, MinoShape::T, MinoShape::T, unseen, &Points, &settings).unwrap();
+
+        // Two Ts can't clear the gap, so they might as well drop into it,
+        // unless an O is coming after them to clear it
+        let ahead = Settings { lookahead: true, ..Settings::default() };
+        let paired = turn(&state, MinoShape::T, MinoShape::T, &UNIFORM, &Points, &ahead).unwrap();
+        let t_next = choose(&only(MinoShape::T));
+        assert_eq!(t_next.mino.points(), paired.mino.points());
+        assert!(t_next.mino.points().iter().any(|(x, _)| *x >= 8));
+
+        let o_next = choose(&only(MinoShape::O));
+        assert!(o_next.mino.points().iter().all(|(x, _)| *x < 8));
+    }
+
+    #[test]
+    fn expectimax_beam_keeps_one_placement() {
+        let state = o_gap();
+        let settings = Settings {
+            expectimax: Some(Expectimax { depth: 1, beam: 1 }),
+            ..Settings::default()
+        };
+        let bot = Counted { bot: Points, count: Cell::new(0) };
+        let chosen = turn(&state, MinoShape::I, MinoShape::O, &only(MinoShape::I), &bot, &settings).unwrap();
+
+        // Only the placement the bot likes best on its own gets searched
+        let greedy = turn(&state, MinoShape::I, MinoShape::O, &UNIFORM, &Points, &Settings::default()).unwrap();
+        assert_eq!(chosen.state.score, greedy.state.score);
+        assert_eq!(o_clears(&chosen.state), 0);
+
+        // Then only the first of the Os it likes best, then the same for the
+        // unseen I, which gets evaluated again at the end
+        let first_best = |placements: &[Placement]| placements.iter()
+            .fold(None, |best: Option<&Placement>, placement| match best {
+                Some(best) if best.state.score >= placement.state.score => Some(best),
+                _ => Some(placement)
+            })
+            .unwrap()
+            .state;
+        let roots = candidates(&state, MinoShape::I, &settings);
+        let after = candidates(&chosen.state, MinoShape::O, &settings);
+        let unseen = candidates(&first_best(&after), MinoShape::I, &settings);
+        assert_eq!(bot.count.get(), roots.len() + after.len() + unseen.len() + 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn expectimax_zero_beam() {
+        let settings = Settings {
+            expectimax: Some(Expectimax { depth: 1, beam: 0 }),
+            ..Settings::default()
+        };
+        turn(&State::new(), MinoShape::T, MinoShape::O, &UNIFORM, &Points, &settings);
+    }
+
+    #[test]
+    fn clears_cancel_pending_garbage() {
+        // Four rows ready for a Tetris, with a cell above so it isn't a
+        // perfect clear
+        let mut player = Player::new(ScriptedRandomizer::new(vec![MinoShape::I, MinoShape::T]));
+        for y in 16..20 {
+            for x in 0..9 {
+                player.state.board.set(x, y, true);
+            }
+        }
+        player.state.board.set(0, 15, true);
+        player.pending = 6;
+
+        let mut holes = GarbageRandomizer::new(0);
+        assert_eq!(player.play(&Points, &Settings::default(), &mut holes), Ok(0));
+        assert_eq!(player.pending, 2);
+        assert_eq!(player.state.lines, 4);
+        assert_eq!(player.state.board.row_mask(19).count_ones(), 1);
+    }
+
+    #[test]
+    fn pending_garbage_comes_in() {
+        let mut player = Player::new(ScriptedRandomizer::new(vec![MinoShape::O, MinoShape::T]));
+        player.pending = 3;
+
+        let mut holes = GarbageRandomizer::new(0);
+        assert_eq!(player.play(&Points, &Settings::default(), &mut holes), Ok(0));
+        assert_eq!(player.pending, 0);
+        for y in 17..20 {
+            assert_eq!(player.state.board.row_mask(y).count_ones(), 9);
+        }
+        let cells: u32 = (0..17).map(|y| player.state.board.row_mask(y).count_ones()).sum();
+        assert_eq!(cells, 4);
+    }
+
+    #[test]
+    fn holding_into_empty_slot_advances_twice() {
+        let sequence = vec![MinoShape::T, MinoShape::O, MinoShape::S, MinoShape::Z, MinoShape::L];
+        let mut player = Player::new(ScriptedRandomizer::new(sequence));
+        let settings = Settings { hold: true, ..Settings::default() };
+
+        let mut holes = GarbageRandomizer::new(0);
+        assert_eq!(player.play(&Holder, &settings, &mut holes), Ok(0));
+        assert_eq!(player.state.hold, Some(MinoShape::T));
+        assert_eq!((player.current, player.next), (MinoShape::S, MinoShape::Z));
+
+        // Swapping with a held piece only uses up the current one
+        assert_eq!(player.play(&Holder, &settings, &mut holes), Ok(0));
+        assert_eq!(player.state.hold, Some(MinoShape::S));
+        assert_eq!((player.current, player.next), (MinoShape::Z, MinoShape::L));
+    }
+
+    #[test]
+    fn versus_topping_out_loses() {
+        let randomizer = || UniformRandomizer::new(1);
+        let settings = Settings::default();
+        assert_eq!(versus(3, &Quitter, &Points, randomizer, &settings), 0.0);
+        assert_eq!(versus(3, &Points, &Quitter, randomizer, &settings), 1.0);
+    }
+}
@@ -0,0 +1,18 @@
+use tetris::*;
+use crate::simulator::*;
+
+/// Rates states by their score alone.
+pub(crate) struct Points;
+
+impl Bot for Points {
+	fn evaluate(&self, state: &State) -> f64 {
+		state.score as f64
+	}
+}
+
+/// Get chances where only `shape` ever comes up.
+pub(crate) fn only(shape: MinoShape) -> [f64; 7] {
+	let mut odds = [0.0; 7];
+	odds[shape.index()] = 1.0;
+	odds
+}
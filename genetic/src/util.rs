@@ -1,3 +1,5 @@
+use rand::random;
+
 /// Simple `cmp` implementation for `f64`.
 /// Panics when given a NaN.
 pub fn f64_cmp(x: f64, y: f64) -> std::cmp::Ordering {
@@ -14,4 +16,19 @@ pub fn f64_cmp(x: f64, y: f64) -> std::cmp::Ordering {
 	else {
 		std::cmp::Ordering::Equal
 	}
-}
\ No newline at end of file
+}
+
+/// Pick an index into `odds` at random, weighted by the chance at each (which
+/// needn't add up to 1).
+pub(crate) fn draw(odds: &[f64; 7]) -> usize {
+	let mut roll = random::<f64>() * odds.iter().sum::<f64>();
+	for (i, probability) in odds.iter().enumerate() {
+		if roll < *probability {
+			return i;
+		}
+		roll -= probability;
+	}
+
+	// Only reachable through rounding
+	odds.iter().rposition(|p| *p > 0.0).unwrap_or(0)
+}
@@ -1,7 +1,10 @@
 mod protocol;
 
 use std::net::{TcpListener, TcpStream};
+use std::io::BufReader;
 use std::io::prelude::*;
+use tetris::*;
+use genetic::*;
 use protocol::*;
 
 fn main() {
@@ -18,16 +21,35 @@ fn main() {
 fn handle(mut stream: TcpStream) {
 	println!("received a connection");
 
-	loop {
-		let mut buffer = Vec::new();
-		stream.read(&mut buffer).unwrap();
-
-		let request = String::from_utf8(buffer).unwrap();
-
-		let (state, mino) = parse_request(&request);
-
-		// ...
+	// No trained weights are saved yet, so play as the hand-tuned bot
+	let bot = Simple::sparring_partner();
+
+	// Requests can arrive split across reads or several at once, so read
+	// them by the line
+	let mut reader = BufReader::new(stream.try_clone().unwrap());
+	while let Some(request) = read_request(&mut reader).unwrap() {
+		let Snapshot { state, current: mino, next } = match parse_request(&request) {
+			Ok(snapshot) => snapshot,
+			Err(e) => {
+				println!("bad request: {}", e);
+				stream.write_all(make_response(Vec::new()).as_bytes()).unwrap();
+				continue;
+			}
+		};
 
-		let response = make_response(Vec::new());
+		// Look ahead whenever the request says what the next piece is
+		let settings = Settings {
+			lookahead: next.is_some(),
+			..Settings::default()
+		};
+		// No searching past the next piece, so the chances of the pieces
+		// after it don't matter
+		let unseen = [1.0 / 7.0; 7];
+		let path = turn(&state, mino, next.unwrap_or(mino), &unseen, &bot, &settings)
+			.map(|placement| placement.path)
+			.unwrap_or_default();
+
+		let response = make_response(steps(mino, &path));
+		stream.write_all(response.as_bytes()).unwrap();
 	}
-}
\ No newline at end of file
+}
@@ -1,94 +1,120 @@
+//! # Tetris Decision Server Protocol
+//! (Writing this down so I don't screw up)
+//! 
+//! ## Requests
+//! The first 20 lines specify the state of the Tetris grid. Each line is to
+//! contain exactly 10 characters, either '.' (denoting empty space) or 'x'
+//! (denoting a block).
+//! The next line specifies the current and next piece information, as a space
+//! separated list of uppercase characters.
+//! The next (and last) line contains three integers, in order: the current
+//! level, the current score, and the current number of lines cleared.
+//! This is the text format of `tetris::Snapshot`.
+//! 
+//! ### Example:
+//! ```
+//! ..........
+//! ..........
+//! ..........
+//! ..........
+//! ..........
+//! ..........
+//! ..........
+//! ..........
+//! ..........
+//! ..........
+//! ..........
+//! ..........
+//! ..........
+//! x.........
+//! xxx...xxx.
+//! xxxxxxxxx.
+//! xxxxxxxxx.
+//! xxxxxxxxx.
+//! xxxxxxxxx.
+//! xxxxxxxxx.
+//! Z I
+//! 18 22800 4
+//! ```
+//! 
+//! ## Responses
+//! Responses are lists of (x, y, r) triples - each one specifies a point and
+//! rotation that the player must navigate to in order to be set up for the
+//! next part of the move. `x` and `y` are absolute grid coordinates, and `r`
+//! is the number of times the player must rotate clockwise. Negative values
+//! for `r` are permitted, e.g. `r` = -1 means rotate once counterclockwise.
+//! 
+//! The first line contains a single integer `n` denoting how many steps there
+//! are to this move.
+//! The next `n` lines contain three integers each - the (x, y, r) triples
+//! described above.
+//! If the request can't be parsed, the response is a move with no steps.
+//! 
+//! ### Example
+//! A T-spin - the T-piece moves to the right a little bit and rotates CCW,
+//! then drops 4 more spaces, then rotates CW to complete the spin.
+//! ```
+//! 3
+//! 7 10 -1
+//! 7 14 0
+//! 7 14 1
+//! ```
+
 use tetris::*;
-use std::io::{Write};
+use std::io::{self, BufRead, Write};
 
-/// # Tetris Decision Server Protocol
-/// (Writing this down so I don't screw up)
-/// 
-/// ## Requests
-/// The first 20 lines specify the state of the Tetris grid. Each line is to
-/// contain exactly 10 characters, either '.' (denoting empty space) or 'x'
-/// (denoting a block).
-/// The next line specifies the current and next piece information, as a space
-/// separated list of uppercase characters.
-/// The next (and last) line contains three integers, in order: the current
-/// level, the current score, and the current number of lines cleared.
-/// 
-/// ### Example:
-/// ```
-/// ..........
-/// ..........
-/// ..........
-/// ..........
-/// ..........
-/// ..........
-/// ..........
-/// ..........
-/// ..........
-/// ..........
-/// ..........
-/// ..........
-/// ..........
-/// x.........
-/// xxx...xxx.
-/// xxxxxxxxx.
-/// xxxxxxxxx.
-/// xxxxxxxxx.
-/// xxxxxxxxx.
-/// xxxxxxxxx.
-/// Z I
-/// 18 22800 4
-/// ```
-/// 
-/// ## Responses
-/// Responses are lists of (x, y, r) triples - each one specifies a point and
-/// rotation that the player must navigate to in order to be set up for the
-/// next part of the move. `x` and `y` are absolute grid coordinates, and `r`
-/// is the number of times the player must rotate clockwise. Negative values
-/// for `r` are permitted, e.g. `r` = -1 means rotate once counterclockwise.
-/// 
-/// The first line contains a single integer `n` denoting how many steps there
-/// are to this move.
-/// The next `n` lines contain three integers each - the (x, y, r) triples
-/// described above.
+/// Number of lines in a request: one for each row of the grid, then the
+/// pieces, then the numbers.
+pub const REQUEST_LINES: usize = 22;
+
+/// Read the next whole request from `reader`, however its lines arrive.
+/// Returns `None` if the connection closes first.
+pub fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Option<String>> {
+	let mut request = String::new();
+	for _ in 0..REQUEST_LINES {
+		if reader.read_line(&mut request)? == 0 {
+			return Ok(None);
+		}
+	}
+
+	Ok(Some(request))
+}
+
+/// Turn a request string into the state and pieces it describes.
+pub fn parse_request(request: &str) -> Result<Snapshot, ParseError> {
+	request.parse()
+}
+
+/// Turn the path that moves a freshly spawned `shape` into place into the
+/// (x, y, r) steps of a response.
 /// 
-/// ### Example
-/// A T-spin - the T-piece moves to the right a little bit and rotates CCW,
-/// then drops 4 more spaces, then rotates CW to complete the spin.
-/// ```
-/// 3
-/// 7 10 -1
-/// 7 14 0
-/// 7 14 1
-/// ```
-
-/// Turn a request string into accompanying state and next piece.
-pub fn parse_request(request: &String) -> (State, MinoShape) {
-	let lines: Vec<&str> = request.lines().collect();
-
-	let mut state = State::new();
-	for y in 0..20 {
-		for (x, cell) in lines[y].chars().enumerate() {
-			state.board.grid[y][x] = match cell {
-				'.' => false,
-				'x' => true,
-				_ => { panic!("Unparseable character at ({}, {}) in board of request", x, y); }
-			}
+/// A new step begins whenever the path switches between dropping and
+/// shifting/rotating, so a tuck or spin shows up as more than one step.
+pub fn steps(shape: MinoShape, path: &[Move]) -> Vec<(i32, i32, i32)> {
+	let mut steps = Vec::new();
+	let mut mino = Mino::new(shape);
+	let mut r = 0;
+
+	for (i, m) in path.iter().enumerate() {
+		mino = m.apply(mino);
+		match m {
+			Move::Clockwise => { r += 1; },
+			Move::CounterClockwise => { r -= 1; },
+			_ => {}
+		}
+
+		let dropping = *m == Move::Down;
+		let last = match path.get(i+1) {
+			Some(next) => (*next == Move::Down) != dropping,
+			None => true
+		};
+		if last {
+			steps.push((mino.x(), mino.y(), r));
+			r = 0;
 		}
 	}
 
-	let mino = match lines[20].chars().nth(0) {
-		Some('I') => MinoShape::I,
-		Some('J') => MinoShape::J,
-		Some('L') => MinoShape::L,
-		Some('O') => MinoShape::O,
-		Some('S') => MinoShape::S,
-		Some('T') => MinoShape::T,
-		Some('Z') => MinoShape::Z,
-		Some(x) => { panic!("Unparseable mino type {} in request", x); },
-		None => { panic!("No next piece given in request"); }
-	};
-
-	(state, mino)
+	steps
 }
 
 /// Turn a path into a response string.
@@ -100,4 +126,72 @@ pub fn make_response(path: Vec<(i32, i32, i32)>) -> String {
 	}
 
 	String::from_utf8(response).unwrap()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod protocol_tests {
+	use std::io::{BufReader, Cursor, Read};
+	use tetris::*;
+	use crate::protocol::*;
+
+	const REQUEST: &str = "..........\n..........\n..........\n..........\n..........\n\
+		..........\n..........\n..........\n..........\n..........\n\
+		..........\n..........\n..........\nx.........\nxxx...xxx.\n\
+		xxxxxxxxx.\nxxxxxxxxx.\nxxxxxxxxx.\nxxxxxxxxx.\nxxxxxxxxx.\n\
+		Z I\n18 22800 4\n";
+
+	/// Hands out its chunks of bytes one read at a time, the way a socket
+	/// might.
+	struct Chunked(Vec<Vec<u8>>);
+
+	impl Read for Chunked {
+		fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+			if self.0.is_empty() {
+				return Ok(0);
+			}
+			let chunk = self.0.remove(0);
+			let n = chunk.len().min(buffer.len());
+			buffer[..n].copy_from_slice(&chunk[..n]);
+			if n < chunk.len() {
+				self.0.insert(0, chunk[n..].to_vec());
+			}
+			Ok(n)
+		}
+	}
+
+	#[test]
+	fn requests_across_reads() {
+		// Split partway through a row
+		let (a, b) = REQUEST.as_bytes().split_at(37);
+		let mut reader = BufReader::new(Chunked(vec![a.to_vec(), b.to_vec()]));
+		assert_eq!(read_request(&mut reader).unwrap().unwrap(), REQUEST);
+		assert_eq!(read_request(&mut reader).unwrap(), None);
+
+		// Two at once
+		let mut reader = Cursor::new(REQUEST.repeat(2));
+		for _ in 0..2 {
+			let request = read_request(&mut reader).unwrap().unwrap();
+			assert!(parse_request(&request).is_ok());
+		}
+		assert_eq!(read_request(&mut reader).unwrap(), None);
+	}
+
+	#[test]
+	fn steps_split_on_drops() {
+		// Rotate and shift, drop, then tuck back
+		let path = [Move::Clockwise, Move::Right, Move::Down, Move::Down, Move::Left];
+		let spawn = Mino::new(MinoShape::T);
+		let (x, y) = (spawn.x(), spawn.y());
+		assert_eq!(steps(MinoShape::T, &path), vec![
+			(x + 1, y, 1),
+			(x + 1, y + 2, 0),
+			(x, y + 2, 0)
+		]);
+
+		// Rotations both ways within a step add up
+		let path = [Move::CounterClockwise, Move::Left, Move::CounterClockwise, Move::Down];
+		assert_eq!(steps(MinoShape::T, &path), vec![(x - 1, y, -2), (x - 1, y + 1, 0)]);
+
+		assert!(steps(MinoShape::T, &[]).is_empty());
+	}
+}
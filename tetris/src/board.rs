@@ -0,0 +1,391 @@
+use crate::*;
+use std::hash::{Hash, Hasher};
+
+/// Playing field `W` cells wide and `H` cells tall, standard 20x10 by default.
+/// Boards can be at most 16 wide.
+///
+/// Each row is packed into the low `W` bits of a `u16` (bit `x` is column
+/// `x`), and the depth of each column is cached so that stack heights are free
+/// to look up.
+///
+/// Filled cells can also remember the shape of the mino that filled them.
+/// Shapes are packed the same way as the rows, in three bitplanes per row
+/// holding one more than the shape's index in `MinoShape::ALL` (0 for cells
+/// that are empty or whose shape isn't known).
+///
+/// Boards are equal when the same cells are filled, whatever shapes filled
+/// them. They hash by their Zobrist hash (see `zobrist`), which is kept up to
+/// date as cells change.
+#[derive(Clone, Copy, Debug)]
+pub struct Board<const W: usize = 10, const H: usize = 20> {
+	rows: [u16; H],
+	shapes: [[u16; 3]; H],
+	depths: [u8; W],
+	hash: u64
+}
+
+impl<const W: usize, const H: usize> PartialEq for Board<W, H> {
+	fn eq(&self, other: &Board<W, H>) -> bool {
+		self.rows == other.rows
+	}
+}
+
+impl<const W: usize, const H: usize> Eq for Board<W, H> {}
+
+impl<const W: usize, const H: usize> Hash for Board<W, H> {
+	fn hash<T: Hasher>(&self, state: &mut T) {
+		state.write_u64(self.hash);
+	}
+}
+
+/// Get the Zobrist key for cell (x, y): a fixed random-looking number, so that
+/// XORing together the keys of the filled cells hashes a board.
+fn zobrist_key(x: usize, y: usize) -> u64 {
+	// SplitMix64 of the cell's index
+	let mut z = ((y << 4 | x) as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+	z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+	z ^ (z >> 31)
+}
+
+impl<const W: usize, const H: usize> Board<W, H> {
+	/// Row mask with every cell filled.
+	pub(crate) const FULL_ROW: u16 = {
+		assert!(W <= 16 && H < 256, "Board is too big");
+		((1u32 << W) - 1) as u16
+	};
+
+	/// Construct an empty board.
+	pub fn blank() -> Board<W, H> {
+		Board {
+			rows: [0; H],
+			shapes: [[0; 3]; H],
+			depths: [H as u8; W],
+			hash: 0
+		}
+	}
+
+	/// Get the number of columns.
+	pub fn width(&self) -> usize {
+		W
+	}
+
+	/// Get the number of rows.
+	pub fn height(&self) -> usize {
+		H
+	}
+
+	/// Get the mino that `shape` spawns as under `rotation`, centered on this
+	/// board. Rotation systems spawn minos for 10-wide boards, so they're
+	/// shifted over by however much the center moves.
+	pub fn spawn<R: RotationSystem>(&self, rotation: &R, shape: MinoShape) -> Mino {
+		rotation.spawn(shape).translated(W as i32/2 - 5, 0)
+	}
+
+	/// Check whether `mino` fits on the board without overlapping anything.
+	pub fn can_place(&self, mino: Mino) -> bool {
+		mino.points().iter()
+			.all(|(x, y)| {
+				let (x, y) = (*x, *y);
+				// The x-coord must be in bounds, but points may be above the grid
+				(0..W as i32).contains(&x) && (y < H as i32) && (y < 0 || self.rows[y as usize] & (1 << x) == 0)
+			})
+	}
+
+	/// Fill in the cells of `mino` and clear any completed lines, returning
+	/// the number of lines cleared.
+	///
+	/// Assumes `mino` can be placed.
+	pub(crate) fn place(&mut self, mino: Mino) -> i32 {
+		let mut top = H;
+		let mut bottom = 0;
+		for (x, y) in mino.points().iter() {
+			let (x, y) = (*x, *y);
+			if y >= 0 {
+				self.rows[y as usize] |= 1 << x;
+				self.set_shape(x as usize, y as usize, Some(mino.shape()));
+				self.hash ^= zobrist_key(x as usize, y as usize);
+				self.depths[x as usize] = self.depths[x as usize].min(y as u8);
+				top = top.min(y as usize);
+				bottom = bottom.max(y as usize + 1);
+			}
+		}
+
+		if top >= bottom || !self.rows[top..bottom].contains(&Self::FULL_ROW) {
+			return 0;
+		}
+
+		// Shift everything above each full row down, starting from the bottom
+		let mut to = bottom;
+		for from in (0..bottom).rev() {
+			if self.rows[from] != Self::FULL_ROW {
+				to -= 1;
+				self.rows[to] = self.rows[from];
+				self.shapes[to] = self.shapes[from];
+			}
+		}
+		let cleared = to;
+		for row in self.rows[..cleared].iter_mut() {
+			*row = 0;
+		}
+		for planes in self.shapes[..cleared].iter_mut() {
+			*planes = [0; 3];
+		}
+
+		self.update_depths();
+		self.rehash();
+
+		cleared as i32
+	}
+
+	/// Get whether cell (x, y) is filled.
+	pub fn get(&self, x: usize, y: usize) -> bool {
+		self.rows[y] & (1 << x) != 0
+	}
+
+	/// Fill or empty cell (x, y). Its shape is forgotten either way.
+	pub fn set(&mut self, x: usize, y: usize, filled: bool) {
+		if self.get(x, y) != filled {
+			self.hash ^= zobrist_key(x, y);
+		}
+		if filled {
+			self.rows[y] |= 1 << x;
+		}
+		else {
+			self.rows[y] &= !(1 << x);
+		}
+		self.set_shape(x, y, None);
+
+		self.update_depths();
+	}
+
+	/// Fill cell (x, y) as if a mino of `shape` had been placed there.
+	pub fn fill(&mut self, x: usize, y: usize, shape: MinoShape) {
+		self.set(x, y, true);
+		self.set_shape(x, y, Some(shape));
+	}
+
+	/// Get the shape of the mino that filled cell (x, y), if it's filled and
+	/// the shape is known. Cells filled by `set` or garbage have no shape.
+	pub fn shape(&self, x: usize, y: usize) -> Option<MinoShape> {
+		let code = (0..3).fold(0, |code, i| code | ((self.shapes[y][i] >> x) & 1) << i);
+		match code {
+			0 => None,
+			code => Some(MinoShape::ALL[code as usize - 1])
+		}
+	}
+
+	/// Push `rows` rows of garbage in from the bottom, each filled except for
+	/// column `hole`, moving everything else up. Returns false if that pushes
+	/// any filled cells out the top. Panics if `hole` isn't a column.
+	pub fn add_garbage(&mut self, rows: usize, hole: usize) -> bool {
+		assert!(hole < W, "Garbage hole {} is outside the board", hole);
+
+		let rows = rows.min(H);
+		let fits = self.rows[..rows].iter().all(|row| *row == 0);
+
+		self.rows.copy_within(rows.., 0);
+		self.shapes.copy_within(rows.., 0);
+		for row in self.rows[H - rows..].iter_mut() {
+			*row = Self::FULL_ROW & !(1 << hole);
+		}
+		for planes in self.shapes[H - rows..].iter_mut() {
+			*planes = [0; 3];
+		}
+
+		self.update_depths();
+		self.rehash();
+
+		fits
+	}
+
+	/// Check whether every cell is empty.
+	pub fn is_empty(&self) -> bool {
+		self.rows.iter().all(|row| *row == 0)
+	}
+
+	/// Get row `y` as a bitmask, where bit `x` is set if cell (x, y) is filled.
+	pub fn row_mask(&self, y: usize) -> u16 {
+		self.rows[y]
+	}
+
+	/// Get the "depth" (number of consecutive empty cells from the top) of
+	/// column `x`.
+	pub fn column_depth(&self, x: usize) -> usize {
+		self.depths[x] as usize
+	}
+
+	/// Unpack the board into a grid of cells, indexed by `[y][x]`.
+	pub fn grid(&self) -> [[bool; W]; H] {
+		let mut grid = [[false; W]; H];
+		for (y, row) in grid.iter_mut().enumerate() {
+			for (x, cell) in row.iter_mut().enumerate() {
+				*cell = self.get(x, y);
+			}
+		}
+
+		grid
+	}
+
+	/// Get the Zobrist hash of the board: the XOR of a fixed key for each
+	/// filled cell. Filling or emptying a cell just XORs in its key, so the
+	/// hash is cheap to keep up to date; it's only recomputed from scratch
+	/// when rows move.
+	pub fn zobrist(&self) -> u64 {
+		self.hash
+	}
+
+	/// Unpack the shapes of the board's cells into a grid, indexed by `[y][x]`.
+	pub fn shapes(&self) -> [[Option<MinoShape>; W]; H] {
+		let mut grid = [[None; W]; H];
+		for (y, row) in grid.iter_mut().enumerate() {
+			for (x, cell) in row.iter_mut().enumerate() {
+				*cell = self.shape(x, y);
+			}
+		}
+
+		grid
+	}
+
+	// Store `shape` (or no shape) as cell (x, y)'s shape
+	fn set_shape(&mut self, x: usize, y: usize, shape: Option<MinoShape>) {
+		let code = match shape {
+			Some(shape) => shape.index() + 1,
+			None => 0
+		};
+		for (i, plane) in self.shapes[y].iter_mut().enumerate() {
+			*plane = (*plane & !(1 << x)) | (((code >> i) & 1) as u16) << x;
+		}
+	}
+
+	// Recompute the Zobrist hash from scratch
+	fn rehash(&mut self) {
+		self.hash = 0;
+		for (y, row) in self.rows.iter().enumerate() {
+			let mut cells = *row;
+			while cells != 0 {
+				self.hash ^= zobrist_key(cells.trailing_zeros() as usize, y);
+				cells &= cells - 1;
+			}
+		}
+	}
+
+	// Recompute the cached column depths from scratch
+	fn update_depths(&mut self) {
+		self.depths = [H as u8; W];
+
+		let mut unseen = Self::FULL_ROW;
+		for (y, row) in self.rows.iter().enumerate() {
+			let mut found = row & unseen;
+			unseen &= !row;
+			while found != 0 {
+				let x = found.trailing_zeros() as usize;
+				self.depths[x] = y as u8;
+				found &= found - 1;
+			}
+
+			if unseen == 0 {
+				break;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod board_tests {
+	use crate::*;
+
+	#[test]
+	#[should_panic]
+	fn garbage_hole_outside() {
+		let mut board: Board = Board::blank();
+		board.add_garbage(1, 10);
+	}
+
+	#[test]
+	fn clears_lines() {
+		let mut board: Board = Board::blank();
+		for x in 0..9 {
+			board.set(x, 19, true);
+			board.set(x, 18, true);
+		}
+		board.set(3, 17, true);
+
+		// Vertical I in the last column fills rows 16-19
+		let mino = Mino::new(MinoShape::I).rotated(1).translated(4, 18);
+		assert!(board.can_place(mino));
+		assert_eq!(board.place(mino), 2);
+
+		assert_eq!(board.row_mask(19), (1 << 3) | (1 << 9));
+		assert_eq!(board.row_mask(18), 1 << 9);
+		assert_eq!(board.row_mask(17), 0);
+		assert_eq!(board.column_depth(3), 19);
+		assert_eq!(board.column_depth(9), 18);
+		assert_eq!(board.column_depth(0), 20);
+	}
+
+	#[test]
+	fn tracks_shapes() {
+		let mut board: Board = Board::blank();
+		for x in 0..9 {
+			board.set(x, 19, true);
+		}
+		board.fill(0, 18, MinoShape::Z);
+		assert_eq!(board.shape(0, 18), Some(MinoShape::Z));
+		assert_eq!(board.shape(0, 19), None);
+
+		// The I's top cells and the Z's cell fall with the clear
+		let mino = Mino::new(MinoShape::I).rotated(1).translated(4, 18);
+		assert_eq!(board.place(mino), 1);
+		assert_eq!(board.shape(0, 19), Some(MinoShape::Z));
+		assert_eq!(board.shape(9, 19), Some(MinoShape::I));
+		assert_eq!(board.shape(9, 17), Some(MinoShape::I));
+		assert_eq!(board.shape(9, 16), None);
+		assert_eq!(board.shapes()[18][9], Some(MinoShape::I));
+
+		// Forgotten when the cell is set by hand
+		board.set(9, 19, true);
+		assert_eq!(board.shape(9, 19), None);
+		assert!(board.add_garbage(1, 0));
+		assert_eq!(board.shape(0, 18), Some(MinoShape::Z));
+		assert_eq!(board.shape(1, 19), None);
+	}
+
+	#[test]
+	fn zobrist() {
+		// Same cells filled by placing and by hand, then by clearing a line
+		let mut placed: Board = Board::blank();
+		let mino = Mino::new(MinoShape::O).translated(0, 18);
+		placed.place(mino);
+
+		let mut set: Board = Board::blank();
+		for (x, y) in mino.points().iter() {
+			set.set(*x as usize, *y as usize, true);
+		}
+		assert_eq!(placed.zobrist(), set.zobrist());
+		assert_eq!(placed, set);
+
+		let mut cleared: Board = Board::blank();
+		for x in (0..4).chain(6..10) {
+			cleared.set(x, 19, true);
+		}
+		assert_eq!(cleared.place(Mino::new(MinoShape::O).translated(0, 18)), 1);
+		let mut bottom: Board = Board::blank();
+		bottom.set(4, 19, true);
+		bottom.set(5, 19, true);
+		assert_eq!(cleared.zobrist(), bottom.zobrist());
+		assert_eq!(cleared, bottom);
+
+		assert_ne!(Board::<10, 20>::blank().zobrist(), set.zobrist());
+		set.set(0, 0, true);
+		assert_ne!(placed.zobrist(), set.zobrist());
+
+		// States go in hash sets
+		let states: std::collections::HashSet<State> = State::new()
+			.placements(MinoShape::O, &NesRotation, &NesScoring).into_iter()
+			.chain(State::new().placements(MinoShape::O, &NesRotation, &NesScoring))
+			.map(|placement| placement.state)
+			.collect();
+		assert_eq!(states.len(), 9);
+	}
+}
@@ -0,0 +1,147 @@
+use crate::*;
+
+/// How a mino got to where it was placed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PlacementKind {
+	/// Moved into position at the top of the board, then dropped straight
+	/// down.
+	Drop,
+	/// Shifted sideways somewhere it couldn't have dropped into (a tuck, or
+	/// slide).
+	Tuck,
+	/// Rotated somewhere it couldn't have dropped into.
+	Spin,
+	/// A T that rotated into place with at least three of the four cells
+	/// diagonal to its center filled. Minis are the ones that don't fill both
+	/// corners on the side the T points to (unless they needed the last,
+	/// farthest kick to get there).
+	TSpin { mini: bool }
+}
+
+impl PlacementKind {
+	/// Get what this placement counts as for T-spin scoring.
+	pub fn t_spin(&self) -> TSpin {
+		match self {
+			PlacementKind::TSpin { mini: true } => TSpin::Mini,
+			PlacementKind::TSpin { mini: false } => TSpin::Full,
+			_ => TSpin::None
+		}
+	}
+}
+
+impl<const W: usize, const H: usize> State<W, H> {
+	/// Classify how `mino` got into place by following `path` from its spawn,
+	/// rotating according to `rotation`.
+	pub fn classify<R: RotationSystem>(&self, mino: Mino, path: &[Move], rotation: &R) -> PlacementKind {
+		let spawn = self.board.spawn(rotation, mino.shape);
+
+		// Could the mino have come straight down from the spawn rows?
+		let mut above = mino;
+		while above.y > spawn.y && self.board.can_place(above.translated(0, -1)) {
+			above = above.translated(0, -1);
+		}
+		if above.y <= spawn.y {
+			return PlacementKind::Drop;
+		}
+
+		let last = match path.iter().rev().find(|m| **m != Move::Down) {
+			Some(m) => *m,
+			None => { return PlacementKind::Drop; }
+		};
+		if last == Move::Left || last == Move::Right {
+			return PlacementKind::Tuck;
+		}
+
+		// T-spins have to end on the rotation
+		if mino.shape != MinoShape::T || path.last() != Some(&last) {
+			return PlacementKind::Spin;
+		}
+
+		let filled = |dx: i32, dy: i32| {
+			let (x, y) = (mino.x + dx, mino.y + dy);
+			!(0..W as i32).contains(&x) || y >= H as i32 || (y >= 0 && self.board.get(x as usize, y as usize))
+		};
+		let corners = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+		if corners.iter().filter(|(dx, dy)| filled(*dx, *dy)).count() < 3 {
+			return PlacementKind::Spin;
+		}
+
+		// The T points towards the cell that isn't opposite another one
+		let cells = mino.cells[mino.rot as usize];
+		let (px, py) = *cells.iter()
+			.find(|(dx, dy)| !cells.contains(&(-dx, -dy)))
+			.unwrap();
+		let front = corners.iter()
+			.filter(|(dx, dy)| dx*px + dy*py > 0)
+			.all(|(dx, dy)| filled(*dx, *dy));
+
+		PlacementKind::TSpin {
+			mini: !front && !self.farthest_kick(mino, path, rotation)
+		}
+	}
+
+	// Check whether the last move of `path` (a rotation) needed the last of
+	// its kicks, replaying the path to find out
+	fn farthest_kick<R: RotationSystem>(&self, mino: Mino, path: &[Move], rotation: &R) -> bool {
+		let mut before = self.board.spawn(rotation, mino.shape);
+		for m in path[..path.len() - 1].iter() {
+			before = match rotation.apply(&self.board, before, *m) {
+				Some(moved) => moved,
+				None => { return false; }
+			};
+		}
+
+		let n = if path.last() == Some(&Move::Clockwise) { 1 } else { -1 };
+		let rotated = before.rotated(n);
+		let kick = (mino.x - rotated.x, mino.y - rotated.y);
+
+		let kicks = rotation.kicks(before, n);
+		kicks.len() > 1 && kicks.last() == Some(&kick)
+	}
+}
+
+#[cfg(test)]
+mod classify_tests {
+	use crate::*;
+
+	#[test]
+	fn t_spin_double() {
+		// Slot for a T pointing down at (4, 18), covered from the left
+		let mut state = State::new();
+		for x in 0..10 {
+			if x != 4 {
+				state.board.set(x, 19, true);
+			}
+			if !(3..=5).contains(&x) {
+				state.board.set(x, 18, true);
+			}
+		}
+		state.board.set(3, 17, true);
+		state.board.set(2, 17, true);
+
+		let placements = state.placements(MinoShape::T, &NesRotation, &GuidelineScoring);
+		let tsd = placements.iter()
+			.find(|p| (p.mino.x, p.mino.y, p.mino.rot) == (4, 18, 2))
+			.unwrap();
+		assert_eq!(tsd.kind, PlacementKind::TSpin { mini: false });
+		assert_eq!(tsd.state.lines, 2);
+		assert_eq!(tsd.state.score, 1200);
+
+		assert!(placements.iter().any(|p| p.kind == PlacementKind::Drop));
+	}
+
+	#[test]
+	fn tucks() {
+		// J tucked under an S
+		let state = State::new();
+		let mino = Mino::new(MinoShape::S).translated(0, 18);
+		let state = state.place(mino).unwrap();
+
+		let placements = state.placements(MinoShape::J, &NesRotation, &NesScoring);
+		let tucks: Vec<&Placement> = placements.iter()
+			.filter(|p| p.kind == PlacementKind::Tuck)
+			.collect();
+		assert_eq!(tucks.len(), 1);
+		assert_eq!(placements.iter().filter(|p| p.kind == PlacementKind::Drop).count(), 34);
+	}
+}
@@ -0,0 +1,241 @@
+use crate::*;
+
+// Standard board features for evaluation functions, mostly as described by
+// Dellacherie and the Tetris AI literature that followed. Heights count up
+// from the floor, so a column with one filled cell at the bottom is 1 high.
+// The walls and floor count as filled wherever that matters.
+
+impl<const W: usize, const H: usize> Board<W, H> {
+	/// Get the height of column `x`: the number of rows from the floor up to
+	/// and including its highest filled cell.
+	pub fn column_height(&self, x: usize) -> usize {
+		H - self.column_depth(x)
+	}
+
+	/// Get the height of every column.
+	pub fn heights(&self) -> [usize; W] {
+		let mut heights = [0; W];
+		for (x, height) in heights.iter_mut().enumerate() {
+			*height = self.column_height(x);
+		}
+
+		heights
+	}
+
+	/// Count the empty cells with a filled cell somewhere above them in the
+	/// same column.
+	pub fn holes(&self) -> usize {
+		let mut above = 0;
+		let mut holes = 0;
+		for y in 0..H {
+			let row = self.row_mask(y);
+			holes += (above & !row).count_ones() as usize;
+			above |= row;
+		}
+
+		holes
+	}
+
+	/// Count the filled cells with a hole somewhere below them in the same
+	/// column, i.e. the cells that have to be cleared to uncover every hole.
+	pub fn covered_cells(&self) -> usize {
+		let mut above = [0; H];
+		for y in 1..H {
+			above[y] = above[y - 1] | self.row_mask(y - 1);
+		}
+
+		let mut below = 0;
+		let mut covered = 0;
+		for y in (0..H).rev() {
+			let row = self.row_mask(y);
+			covered += (below & row).count_ones() as usize;
+			below |= above[y] & !row;
+		}
+
+		covered
+	}
+
+	/// Count the times a filled cell is next to an empty one horizontally,
+	/// with the walls counting as filled. Every empty row counts for 2.
+	pub fn row_transitions(&self) -> usize {
+		let walls = 1 | (1 << (W + 1));
+		let mask = (1u32 << (W + 1)) - 1;
+
+		(0..H)
+			.map(|y| {
+				let row = ((self.row_mask(y) as u32) << 1) | walls;
+				((row ^ (row >> 1)) & mask).count_ones() as usize
+			})
+			.sum()
+	}
+
+	/// Count the times a filled cell is next to an empty one vertically, with
+	/// the floor counting as filled and the space above the board as empty.
+	pub fn column_transitions(&self) -> usize {
+		let mut transitions = self.row_mask(0).count_ones() as usize;
+		for y in 1..H {
+			transitions += (self.row_mask(y - 1) ^ self.row_mask(y)).count_ones() as usize;
+		}
+		transitions + (self.row_mask(H - 1) ^ Self::FULL_ROW).count_ones() as usize
+	}
+
+	/// Get the sum of the differences in height between neighboring columns.
+	pub fn bumpiness(&self) -> usize {
+		(1..W)
+			.map(|x| (self.column_height(x - 1) as i32 - self.column_height(x) as i32).unsigned_abs() as usize)
+			.sum()
+	}
+
+	/// Get the depth of the well in each column: how far the column's surface
+	/// is below the lower of its neighbors, or 0 if it isn't below both.
+	pub fn well_depths(&self) -> [usize; W] {
+		let heights = self.heights();
+
+		let mut wells = [0; W];
+		for (x, well) in wells.iter_mut().enumerate() {
+			let left = if x == 0 { H } else { heights[x - 1] };
+			let right = if x == W - 1 { H } else { heights[x + 1] };
+			*well = min(left, right).saturating_sub(heights[x]);
+		}
+
+		wells
+	}
+
+	/// Sum 1 + 2 + ... + depth over every well, so deep wells count for much
+	/// more than several shallow ones.
+	pub fn cumulative_wells(&self) -> usize {
+		self.well_depths().iter()
+			.map(|depth| depth*(depth + 1) / 2)
+			.sum()
+	}
+
+	/// Count the wells at least 3 deep, which only an I can fill without
+	/// leaving a hole.
+	pub fn i_dependencies(&self) -> usize {
+		self.well_depths().iter()
+			.filter(|depth| **depth >= 3)
+			.count()
+	}
+
+	/// Check whether a vertical I dropped straight down some column would
+	/// clear four lines.
+	pub fn tetris_ready(&self) -> bool {
+		(0..W).any(|x| {
+			let depth = self.column_depth(x);
+			let row = Self::FULL_ROW & !(1 << x);
+			depth >= 4 && (depth - 4..depth).all(|y| self.row_mask(y) == row)
+		})
+	}
+}
+
+impl<const W: usize, const H: usize> Placement<W, H> {
+	/// Get the height the mino landed at, measured to the middle of the mino
+	/// (so a vertical I on the floor lands at 2.5).
+	pub fn landing_height(&self) -> f64 {
+		let points = self.mino.points();
+		let top = points.iter().map(|(_, y)| *y).min().unwrap();
+		let bottom = points.iter().map(|(_, y)| *y).max().unwrap();
+
+		H as f64 - (top + bottom) as f64 / 2.0
+	}
+
+	/// Get the number of lines this placement cleared from `before`, times
+	/// the number of the mino's own cells that were cleared with them.
+	pub fn eroded_cells(&self, before: &State<W, H>) -> usize {
+		let points = self.mino.points();
+
+		let mut lines = 0;
+		let mut cells = 0;
+		for y in 0..H {
+			let own = points.iter()
+				.filter(|(_, py)| *py == y as i32)
+				.fold(0, |row, (x, _)| row | (1 << x));
+			if own != 0 && before.board.row_mask(y) | own == Board::<W, H>::FULL_ROW {
+				lines += 1;
+				cells += own.count_ones() as usize;
+			}
+		}
+
+		lines*cells
+	}
+}
+
+#[cfg(test)]
+mod features_tests {
+	use crate::*;
+
+	#[test]
+	fn board_features() {
+		let board: Board<6, 6> = "\
+......
+......
+x.....
+x..x..
+.x.xx.
+xx.x.x
+".parse().unwrap();
+
+		assert_eq!(board.heights(), [4, 2, 0, 3, 2, 1]);
+		assert_eq!(board.holes(), 2);
+		assert_eq!(board.covered_cells(), 3);
+		assert_eq!(board.row_transitions(), 2 + 2 + 2 + 4 + 6 + 4);
+		assert_eq!(board.column_transitions(), 3 + 1 + 1 + 1 + 3 + 1);
+		assert_eq!(board.bumpiness(), 2 + 2 + 3 + 1 + 1);
+		assert_eq!(board.well_depths(), [0, 0, 2, 0, 0, 1]);
+		assert_eq!(board.cumulative_wells(), 3 + 1);
+		assert_eq!(board.i_dependencies(), 0);
+		assert!(!board.tetris_ready());
+
+		let blank: Board = Board::blank();
+		assert_eq!((blank.holes(), blank.covered_cells(), blank.bumpiness()), (0, 0, 0));
+		assert_eq!(blank.row_transitions(), 2*20);
+		assert_eq!(blank.column_transitions(), 10);
+	}
+
+	#[test]
+	fn tetris_ready() {
+		let mut board: Board<4, 6> = "\
+....
+....
+xxx.
+xxx.
+xxx.
+xxx.
+".parse().unwrap();
+
+		assert!(board.tetris_ready());
+		assert_eq!(board.well_depths(), [0, 0, 0, 4]);
+		assert_eq!(board.i_dependencies(), 1);
+		assert_eq!(board.cumulative_wells(), 10);
+
+		// Three rows isn't enough
+		board.set(3, 2, true);
+		assert!(!board.tetris_ready());
+		board.set(3, 2, false);
+
+		// Neither is a well that's covered up
+		board.set(3, 0, true);
+		assert!(!board.tetris_ready());
+		assert_eq!(board.holes(), 5);
+		assert_eq!(board.covered_cells(), 1);
+	}
+
+	#[test]
+	fn placement_features() {
+		let mut state = State::new();
+		for y in 16..20 {
+			for x in 0..9 {
+				state.board.set(x, y, true);
+			}
+		}
+
+		let placements = state.placements(MinoShape::I, &NesRotation, &NesScoring);
+		let tetris = placements.iter().find(|p| p.state.lines == 4).unwrap();
+		assert_eq!(tetris.landing_height(), 2.5);
+		assert_eq!(tetris.eroded_cells(&state), 4*4);
+
+		let flat = placements.iter().find(|p| p.state.lines == 0 && p.mino.points()[0].1 == 15).unwrap();
+		assert_eq!(flat.landing_height(), 5.0);
+		assert_eq!(flat.eroded_cells(&state), 0);
+	}
+}
@@ -0,0 +1,599 @@
+use crate::*;
+use std::error::Error;
+use std::fmt;
+
+// Fumen (v115) packs everything into base 64 digits, little end first. Each
+// page is a field, stored as a run-length encoded diff from the field the
+// last page left behind, then the piece on the page and some flags, then
+// the page's comment if it changed.
+//
+// The field is 23 rows of 10 plus a hidden garbage row, read from the top
+// left. Cells hold colors: 0 is empty, 1-7 are pieces and 8 is gray.
+
+const ENCODE_TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Rows in a fumen field, not counting the garbage row.
+const FIELD_TOP: usize = 23;
+
+/// Cells in a fumen field, counting the garbage row.
+const FIELD_BLOCKS: usize = (FIELD_TOP + 1) * 10;
+
+const GRAY: u8 = 8;
+
+/// One page of a fumen: a state, the mino placed in it (if any), and a
+/// comment. Minos lock at the end of their page, so the next page starts from
+/// the board they leave behind.
+#[derive(Clone, Debug)]
+pub struct FumenPage {
+	pub state: State,
+	pub mino: Option<Mino>,
+	pub comment: String
+}
+
+/// Reasons that a string can fail to decode as a fumen.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FumenError {
+	/// The string doesn't start with a supported version, i.e. "v115@".
+	UnknownVersion,
+	/// A character that isn't part of fumen's base 64.
+	BadCharacter(char),
+	/// The data ends partway through a page.
+	Truncated,
+	/// A page's field has a cell outside the range of colors.
+	BadField,
+	/// A page's piece doesn't fit on the field.
+	BadPiece,
+	/// A page has filled cells above the 20 rows of a `Board`.
+	TooTall
+}
+
+impl fmt::Display for FumenError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			FumenError::UnknownVersion => write!(f, "Not a v115 fumen"),
+			FumenError::BadCharacter(c) => write!(f, "Unexpected character {} in fumen", c),
+			FumenError::Truncated => write!(f, "Fumen ends partway through a page"),
+			FumenError::BadField => write!(f, "Fumen field has an unknown color"),
+			FumenError::BadPiece => write!(f, "Fumen piece doesn't fit on the field"),
+			FumenError::TooTall => write!(f, "Fumen field is taller than 20 rows")
+		}
+	}
+}
+
+impl Error for FumenError {}
+
+/// Encode `pages` as a fumen string, which fumen viewers can show.
+///
+/// Pieces are converted to their SRS orientation, since that's what fumen
+/// shows. Cells are colored by their shape (see `Board::shape`), or if it
+/// isn't known, by the piece that filled them on an earlier page, or gray.
+pub fn encode_fumen(pages: &[FumenPage]) -> String {
+	let mut values = Vec::new();
+	let mut prev = [0; FIELD_BLOCKS];
+	let mut prev_comment = "";
+	// Index in `values` of the count of pages repeating the last field
+	let mut repeat: Option<usize> = None;
+
+	for page in pages.iter() {
+		let mut field = [0; FIELD_BLOCKS];
+		field[FIELD_TOP*10..].copy_from_slice(&prev[FIELD_TOP*10..]);
+		for y in 0..20 {
+			for x in 0..10 {
+				let i = field_index(x, 19 - y as i32).unwrap();
+				if page.state.board.get(x as usize, y) {
+					field[i] = match page.state.board.shape(x as usize, y) {
+						Some(shape) => fumen_color(shape),
+						None if prev[i] != 0 => prev[i],
+						None => GRAY
+					};
+				}
+			}
+		}
+
+		let mut diff = Vec::new();
+		if encode_field(&prev, &field, &mut diff) {
+			values.extend(diff);
+			repeat = None;
+		}
+		else {
+			match repeat {
+				// Pages repeating the field don't have to encode it
+				Some(i) if values[i] < 63 => { values[i] += 1; },
+				_ => {
+					values.extend(diff);
+					values.push(0);
+					repeat = Some(values.len() - 1);
+				}
+			}
+		}
+
+		let (piece, rotation, position) = match page.mino.and_then(fumen_piece) {
+			Some((piece, rotation, (x, y))) => {
+				for (x, y) in fumen_cells(piece, rotation, x, y).iter() {
+					if let Some(i) = field_index(*x, *y) {
+						field[i] = piece;
+					}
+				}
+				(piece, rotation, (FIELD_TOP as i32 - y - 1)*10 + x)
+			},
+			None => (0, 0, 0)
+		};
+
+		let comment = page.comment != prev_comment;
+		// Flags, highest first: not locking, comment, colorize, mirror, rise
+		let flags = if comment { 0b01100 } else { 0b00100 };
+		let action = ((flags*FIELD_BLOCKS as u32 + position as u32)*4 + rotation as u32)*8 + piece as u32;
+		poke(&mut values, action, 3);
+
+		if comment {
+			let escaped: Vec<u32> = escape(&page.comment).bytes().take(4095).map(|c| c as u32 - 32).collect();
+			poke(&mut values, escaped.len() as u32, 2);
+			for chunk in escaped.chunks(4) {
+				let value = chunk.iter().rev().fold(0, |value, c| value*96 + c);
+				poke(&mut values, value, 5);
+			}
+			prev_comment = &page.comment;
+		}
+
+		clear_lines(&mut field);
+		prev = field;
+	}
+
+	let data: Vec<u8> = values.iter().map(|value| ENCODE_TABLE[*value as usize]).collect();
+	let data = String::from_utf8(data).unwrap();
+
+	// Viewers expect a '?' after the first 42 characters and every 47 after
+	let mut fumen = String::from("v115@");
+	let (head, mut tail) = data.split_at(data.len().min(42));
+	fumen.push_str(head);
+	while !tail.is_empty() {
+		let (chunk, rest) = tail.split_at(tail.len().min(47));
+		fumen.push('?');
+		fumen.push_str(chunk);
+		tail = rest;
+	}
+
+	fumen
+}
+
+/// Decode the pages of a fumen string. Minos come out with SRS orientations.
+///
+/// Only fields that fit in a 20-row `Board` can be decoded, and the garbage
+/// row is left out. Cells keep the shapes their colors stand for, except gray
+/// ones, which have no shape.
+pub fn decode_fumen(fumen: &str) -> Result<Vec<FumenPage>, FumenError> {
+	let data = fumen.trim()
+		.strip_prefix("v115@")
+		.ok_or(FumenError::UnknownVersion)?;
+	let mut values = Vec::new();
+	for c in data.chars().filter(|c| *c != '?') {
+		match ENCODE_TABLE.iter().position(|e| *e as char == c) {
+			Some(value) => { values.push(value as u32); },
+			None => { return Err(FumenError::BadCharacter(c)); }
+		}
+	}
+	let mut values = values.into_iter();
+
+	let mut pages = Vec::new();
+	let mut prev = [0; FIELD_BLOCKS];
+	let mut comment = String::new();
+	let mut repeat = 0;
+
+	while values.len() > 0 {
+		let mut field = prev;
+		if repeat > 0 {
+			repeat -= 1;
+		}
+		else {
+			let mut i = 0;
+			while i < FIELD_BLOCKS {
+				let run = poll(&mut values, 2)? as usize;
+				let (diff, n) = (run / FIELD_BLOCKS, run % FIELD_BLOCKS + 1);
+				if i + n > FIELD_BLOCKS {
+					return Err(FumenError::BadField);
+				}
+				for cell in field[i..i + n].iter_mut() {
+					*cell = (*cell as usize + diff).checked_sub(8)
+						.filter(|color| *color <= GRAY as usize)
+						.ok_or(FumenError::BadField)? as u8;
+				}
+				if diff == 8 && n == FIELD_BLOCKS {
+					repeat = poll(&mut values, 1)?;
+				}
+				i += n;
+			}
+		}
+
+		let mut action = poll(&mut values, 3)?;
+		let mut flag = || {
+			let set = action % 2 == 1;
+			action /= 2;
+			set
+		};
+		let piece = (flag() as u8) | (flag() as u8) << 1 | (flag() as u8) << 2;
+		let rotation = (flag() as usize) | (flag() as usize) << 1;
+		let position = action % FIELD_BLOCKS as u32;
+		action /= FIELD_BLOCKS as u32;
+		let rise = action % 2 == 1;
+		let mirror = (action >> 1) % 2 == 1;
+		let has_comment = (action >> 3) % 2 == 1;
+		let lock = (action >> 4) % 2 == 0;
+
+		if has_comment {
+			let length = poll(&mut values, 2)? as usize;
+			let mut escaped = String::new();
+			for _ in 0..length.div_ceil(4) {
+				let mut value = poll(&mut values, 5)?;
+				for _ in 0..4 {
+					escaped.push((value % 96 + 32) as u8 as char);
+					value /= 96;
+				}
+			}
+			escaped.truncate(length);
+			comment = unescape(&escaped);
+		}
+
+		let mut board = Board::blank();
+		for (i, cell) in field[..FIELD_TOP*10].iter().enumerate() {
+			let (x, y) = (i % 10, FIELD_TOP - i / 10 - 1);
+			if *cell != 0 {
+				if y >= 20 {
+					return Err(FumenError::TooTall);
+				}
+				match color_shape(*cell) {
+					Some(shape) => { board.fill(x, 19 - y, shape); },
+					None => { board.set(x, 19 - y, true); }
+				}
+			}
+		}
+
+		let mino = if piece == 0 {
+			None
+		}
+		else {
+			let x = position as i32 % 10;
+			let y = FIELD_TOP as i32 - position as i32 / 10 - 1;
+			let (mino, cells) = srs_mino(piece, rotation, x, y).ok_or(FumenError::BadPiece)?;
+
+			if lock {
+				for (x, y) in cells.iter() {
+					let i = field_index(*x, *y).ok_or(FumenError::BadPiece)?;
+					field[i] = piece;
+				}
+			}
+			Some(mino)
+		};
+
+		pages.push(FumenPage {
+			state: State::with_board(board, 0),
+			mino,
+			comment: comment.clone()
+		});
+
+		if lock {
+			clear_lines(&mut field);
+			if rise {
+				// The garbage row rises into the field
+				field.copy_within(10.., 0);
+				for cell in field[FIELD_TOP*10..].iter_mut() {
+					*cell = 0;
+				}
+			}
+			if mirror {
+				for row in field[..FIELD_TOP*10].chunks_mut(10) {
+					row.reverse();
+				}
+			}
+		}
+		prev = field;
+	}
+
+	Ok(pages)
+}
+
+/// Get the index of the field cell in column `x` and row `y`, counting up from
+/// the bottom of the field, if it's on the field.
+fn field_index(x: i32, y: i32) -> Option<usize> {
+	if (0..10).contains(&x) && (0..FIELD_TOP as i32).contains(&y) {
+		Some((FIELD_TOP - y as usize - 1)*10 + x as usize)
+	}
+	else {
+		None
+	}
+}
+
+/// Encode the diff from `prev` to `field` onto `values`, and return whether
+/// anything changed.
+fn encode_field(prev: &[u8; FIELD_BLOCKS], field: &[u8; FIELD_BLOCKS], values: &mut Vec<u32>) -> bool {
+	let diff = |i: usize| (field[i] as usize + 8 - prev[i] as usize) as u32;
+
+	let mut start = 0;
+	for i in 1..=FIELD_BLOCKS {
+		if i == FIELD_BLOCKS || diff(i) != diff(start) {
+			poke(values, diff(start)*FIELD_BLOCKS as u32 + (i - start - 1) as u32, 2);
+			start = i;
+		}
+	}
+
+	prev != field
+}
+
+/// Clear the full rows of the field (not counting the garbage row).
+fn clear_lines(field: &mut [u8; FIELD_BLOCKS]) {
+	let mut to = FIELD_TOP;
+	for from in (0..FIELD_TOP).rev() {
+		let row = field[from*10..(from + 1)*10].to_vec();
+		if row.contains(&0) {
+			to -= 1;
+			field[to*10..(to + 1)*10].copy_from_slice(&row);
+		}
+	}
+	for cell in field[..to*10].iter_mut() {
+		*cell = 0;
+	}
+}
+
+/// Push `value` onto `values` as `digits` base 64 digits.
+fn poke(values: &mut Vec<u32>, value: u32, digits: usize) {
+	let mut value = value;
+	for _ in 0..digits {
+		values.push(value % 64);
+		value /= 64;
+	}
+}
+
+/// Take a value of `digits` base 64 digits off the front of `values`.
+fn poll<I: Iterator<Item = u32>>(values: &mut I, digits: usize) -> Result<u32, FumenError> {
+	let mut value = 0;
+	for i in 0..digits {
+		value += values.next().ok_or(FumenError::Truncated)? << (6*i);
+	}
+
+	Ok(value)
+}
+
+/// Fumen's number for `shape`, which it also uses as the shape's color.
+fn fumen_color(shape: MinoShape) -> u8 {
+	match shape {
+		MinoShape::I => 1,
+		MinoShape::L => 2,
+		MinoShape::O => 3,
+		MinoShape::Z => 4,
+		MinoShape::T => 5,
+		MinoShape::J => 6,
+		MinoShape::S => 7
+	}
+}
+
+/// Get the shape fumen colors with `color`, if any.
+fn color_shape(color: u8) -> Option<MinoShape> {
+	MinoShape::ALL.iter().copied().find(|shape| fumen_color(*shape) == color)
+}
+
+/// Get the cells (counting up from the bottom) of fumen piece `piece` with
+/// rotation `rotation` and position (x, y).
+///
+/// Fumen rotations go 180, right, spawn, left. Positions are mostly SRS
+/// rotation centers, but minos with more than one center that gives the same
+/// cells use a single one of them.
+fn fumen_cells(piece: u8, rotation: usize, x: i32, y: i32) -> [(i32, i32); 4] {
+	let spawn = match piece {
+		1 => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+		2 => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+		3 => [(0, 0), (1, 0), (0, 1), (1, 1)],
+		4 => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+		5 => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+		6 => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+		_ => [(0, 0), (-1, 0), (0, 1), (1, 1)]
+	};
+	let (dx, dy) = match (piece, rotation) {
+		(3, 0) => (1, 0),
+		(3, 2) => (0, -1),
+		(3, 3) => (1, -1),
+		(1, 0) => (1, 0),
+		(1, 3) => (0, -1),
+		(7, 1) => (-1, 0),
+		(7, 2) => (0, -1),
+		(4, 2) => (0, -1),
+		(4, 3) => (1, 0),
+		_ => (0, 0)
+	};
+
+	let mut cells = spawn;
+	for cell in cells.iter_mut() {
+		let (cx, cy) = *cell;
+		let (cx, cy) = match rotation {
+			0 => (-cx, -cy),
+			1 => (cy, -cx),
+			2 => (cx, cy),
+			_ => (-cy, cx)
+		};
+		*cell = (x + dx + cx, y + dy + cy);
+	}
+
+	cells
+}
+
+/// Get the SRS mino (and its cells on the field) for a fumen piece.
+fn srs_mino(piece: u8, rotation: usize, x: i32, y: i32) -> Option<(Mino, [(i32, i32); 4])> {
+	let shape = color_shape(piece)?;
+	let cells = fumen_cells(piece, rotation, x, y);
+	let points: Vec<(i32, i32)> = cells.iter().map(|(x, y)| (*x, 19 - y)).collect();
+
+	let srs = SrsRotation::cells(shape);
+	let rot = [2, 1, 0, 3][rotation] % srs.len();
+	let offsets = srs[rot];
+	let (x, y) = (points[0].0 - offsets[0].0, points[0].1 - offsets[0].1);
+	let mino = Mino::with_cells(shape, srs, rot as i32, x, y);
+
+	if same_cells(&mino.points(), &points) {
+		Some((mino, cells))
+	}
+	else {
+		// The first points don't line up, try the others
+		(1..4).map(|i| (points[0].0 - offsets[i].0, points[0].1 - offsets[i].1))
+			.map(|(x, y)| Mino::with_cells(shape, srs, rot as i32, x, y))
+			.find(|mino| same_cells(&mino.points(), &points))
+			.map(|mino| (mino, cells))
+	}
+}
+
+/// Get the fumen piece, rotation and position matching `mino`'s cells.
+fn fumen_piece(mino: Mino) -> Option<(u8, usize, (i32, i32))> {
+	let piece = fumen_color(mino.shape);
+	let cells: Vec<(i32, i32)> = mino.points().iter().map(|(x, y)| (*x, 19 - y)).collect();
+
+	for rotation in [2, 1, 0, 3].iter() {
+		let offsets = fumen_cells(piece, *rotation, 0, 0);
+		for (dx, dy) in offsets.iter() {
+			let (x, y) = (cells[0].0 - dx, cells[0].1 - dy);
+			if same_cells(&fumen_cells(piece, *rotation, x, y), &cells) {
+				return Some((piece, *rotation, (x, y)));
+			}
+		}
+	}
+
+	None
+}
+
+fn same_cells(a: &[(i32, i32)], b: &[(i32, i32)]) -> bool {
+	a.iter().all(|cell| b.contains(cell)) && b.iter().all(|cell| a.contains(cell))
+}
+
+/// Escape `text` the way JavaScript's `escape` does, which is how fumen
+/// stores comments.
+fn escape(text: &str) -> String {
+	let mut escaped = String::new();
+	for c in text.chars() {
+		if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) {
+			escaped.push(c);
+		}
+		else if (c as u32) < 256 {
+			escaped.push_str(&format!("%{:02X}", c as u32));
+		}
+		else {
+			let mut units = [0; 2];
+			for unit in c.encode_utf16(&mut units).iter() {
+				escaped.push_str(&format!("%u{:04X}", unit));
+			}
+		}
+	}
+
+	escaped
+}
+
+/// Undo `escape`.
+fn unescape(escaped: &str) -> String {
+	let mut units = Vec::new();
+	let mut rest = escaped;
+	while let Some(c) = rest.chars().next() {
+		let code = if rest.starts_with("%u") {
+			rest.get(2..6).and_then(|hex| u16::from_str_radix(hex, 16).ok()).map(|unit| (unit, 6))
+		}
+		else if c == '%' {
+			rest.get(1..3).and_then(|hex| u16::from_str_radix(hex, 16).ok()).map(|unit| (unit, 3))
+		}
+		else {
+			None
+		};
+
+		match code {
+			Some((unit, length)) => {
+				units.push(unit);
+				rest = &rest[length..];
+			},
+			None => {
+				units.push(c as u16);
+				rest = &rest[c.len_utf8()..];
+			}
+		}
+	}
+
+	String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod fumen_tests {
+	use crate::*;
+
+	#[test]
+	fn empty() {
+		let pages = decode_fumen("v115@vhAAgH").unwrap();
+		assert_eq!(pages.len(), 1);
+		assert!(pages[0].state.board.is_empty());
+		assert!(pages[0].mino.is_none());
+
+		let page = FumenPage {
+			state: State::new(),
+			mino: None,
+			comment: String::new()
+		};
+		// Repeated fields are only encoded once
+		let fumen = encode_fumen(&[page.clone(), page.clone(), page.clone()]);
+		assert_eq!(fumen, "v115@vhCAgHAgHAgH");
+		assert_eq!(decode_fumen(&fumen).unwrap().len(), 3);
+
+		assert_eq!(encode_fumen(&[page]), "v115@vhAAgH");
+
+		assert_eq!(decode_fumen("v110@vhAAgH").unwrap_err(), FumenError::UnknownVersion);
+		assert_eq!(decode_fumen("v115@vhAAg").unwrap_err(), FumenError::Truncated);
+		assert_eq!(decode_fumen("v115@vh!AgH").unwrap_err(), FumenError::BadCharacter('!'));
+	}
+
+	#[test]
+	fn round_trip() {
+		// A game's worth of NES placements
+		let mut pages = Vec::new();
+		let mut state = State::new();
+		let mut randomizer = NesRandomizer::new(NesRandomizer::POWER_ON_SEED);
+		for i in 0..30 {
+			// Vary between the lowest placements, to keep the stack down
+			let placements = state.placements(randomizer.next(), &NesRotation, &NesScoring);
+			let lowest = placements.iter().map(|p| p.mino.y).max().unwrap();
+			let placements: Vec<&Placement> = placements.iter().filter(|p| p.mino.y == lowest).collect();
+			let placement = placements[i % placements.len()].clone();
+			pages.push(FumenPage {
+				state,
+				mino: Some(placement.mino),
+				comment: if i % 10 == 0 { format!("Page {}: 100% ✓", i) } else { String::new() }
+			});
+			state = placement.state;
+		}
+
+		let fumen = encode_fumen(&pages);
+		assert!(fumen.split('?').skip(1).all(|chunk| chunk.len() <= 47));
+		let decoded = decode_fumen(&fumen).unwrap();
+		assert_eq!(decoded.len(), pages.len());
+
+		for (page, decoded) in pages.iter().zip(decoded.iter()) {
+			assert_eq!(page.state.board.to_string(), decoded.state.board.to_string());
+			assert_eq!(page.state.board.shapes(), decoded.state.board.shapes());
+			assert_eq!(page.comment, decoded.comment);
+
+			let mut expected = page.mino.unwrap().points();
+			let mut actual = decoded.mino.unwrap().points();
+			expected.sort();
+			actual.sort();
+			assert_eq!(expected, actual);
+			assert_eq!(decoded.mino.unwrap().shape, page.mino.unwrap().shape);
+		}
+		assert_eq!(decoded[10].comment, "Page 10: 100% ✓");
+	}
+
+	#[test]
+	fn pieces() {
+		// T flat side down at the bottom, pointing up
+		let mino = SrsRotation.spawn(MinoShape::T).translated(0, 19);
+		let page = FumenPage {
+			state: State::new(),
+			mino: Some(mino),
+			comment: String::new()
+		};
+		let fumen = encode_fumen(&[page]);
+		assert_eq!(fumen, "v115@vhAVQJ");
+
+		let decoded = decode_fumen(&fumen).unwrap();
+		let decoded = decoded[0].mino.unwrap();
+		assert_eq!((decoded.x, decoded.y, decoded.rot), (4, 19, 0));
+	}
+}
@@ -0,0 +1,139 @@
+use crate::*;
+
+/// Rows of garbage sent for a combo, indexed by the number of clears in a row
+/// before the one sending. Longer combos send as much as the last entry.
+const COMBO_GARBAGE: [i32; 12] = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+
+/// Picks the hole column for incoming garbage. Each batch of rows shares one
+/// hole, chosen uniformly at random.
+#[derive(Clone, Copy, Debug)]
+pub struct GarbageRandomizer {
+	rng: Rng
+}
+
+impl GarbageRandomizer {
+	pub fn new(seed: u64) -> GarbageRandomizer {
+		GarbageRandomizer {
+			rng: Rng::new(seed)
+		}
+	}
+
+	/// Pick the hole for the next batch of garbage on a board `width` wide.
+	pub fn hole(&mut self, width: usize) -> usize {
+		self.rng.below(width)
+	}
+}
+
+/// Get the number of garbage rows sent to the opponent when `clear` happens in
+/// `state` (from before the placement, as with `ScoringRule`), following the
+/// modern guideline attack table.
+///
+/// Doubles, triples and Tetrises send 1, 2 and 4 rows; T-spins send twice
+/// their lines (a mini double sends 1). Back-to-back clears send one more,
+/// combos send extra according to `COMBO_GARBAGE`, and perfect clears send 10.
+pub fn garbage_sent<const W: usize, const H: usize>(state: &State<W, H>, clear: &Clear) -> i32 {
+	if clear.lines == 0 {
+		return 0;
+	}
+
+	let base = match (clear.t_spin, clear.lines) {
+		(TSpin::None, 4) => 4,
+		(TSpin::None, n) => n - 1,
+		(TSpin::Mini, n) => n - 1,
+		(TSpin::Full, n) => 2*n
+	};
+	let back_to_back = if state.back_to_back && clear.is_difficult() { 1 } else { 0 };
+	let combo = COMBO_GARBAGE[(state.combo as usize).min(COMBO_GARBAGE.len() - 1)];
+	let perfect = if clear.perfect { 10 } else { 0 };
+
+	base + back_to_back + combo + perfect
+}
+
+impl<const W: usize, const H: usize> State<W, H> {
+	/// Produce a new state with `rows` rows of garbage pushed in from the
+	/// bottom, each with a hole in column `hole`.
+	///
+	/// Gives `None` if the garbage pushes the stack out the top.
+	pub fn add_garbage(&self, rows: usize, hole: usize) -> Option<State<W, H>> {
+		let mut board = self.board;
+		if !board.add_garbage(rows, hole) {
+			return None;
+		}
+
+		Some(State {
+			board,
+			..*self
+		})
+	}
+}
+
+impl<const W: usize, const H: usize> Placement<W, H> {
+	/// Reconstruct what happened when this placement was made from `before`.
+	pub fn clear(&self, before: &State<W, H>) -> Clear {
+		let lines = self.state.lines - before.lines;
+
+		Clear {
+			lines,
+			push_down: self.path.iter().rev()
+				.take_while(|m| **m == Move::Down)
+				.count() as i32,
+			t_spin: self.kind.t_spin(),
+			perfect: lines > 0 && self.state.board.is_empty()
+		}
+	}
+}
+
+#[cfg(test)]
+mod garbage_tests {
+	use crate::*;
+
+	#[test]
+	fn add_garbage() {
+		let full = (1 << 10) - 1;
+		let mut state = State::new();
+		state.board.set(0, 19, true);
+
+		let state = state.add_garbage(2, 3).unwrap();
+		assert_eq!(state.board.row_mask(19), full & !(1 << 3));
+		assert_eq!(state.board.row_mask(18), full & !(1 << 3));
+		assert_eq!(state.board.row_mask(17), 1);
+		assert_eq!(state.column_depth(0), 17);
+		assert_eq!(state.column_depth(3), 20);
+
+		// A vertical I in the hole clears both rows
+		let mino = Mino::new(MinoShape::I).rotated(1).translated(-2, 18);
+		assert_eq!(state.place(mino).unwrap().lines, 2);
+
+		// Full column gets pushed out
+		let mut state = State::new();
+		for y in 0..20 {
+			state.board.set(5, y, true);
+		}
+		assert!(state.add_garbage(1, 0).is_none());
+
+		let mut holes = GarbageRandomizer::new(7);
+		assert!((0..100).all(|_| holes.hole(10) < 10));
+	}
+
+	#[test]
+	fn attacks() {
+		let clear = |lines, t_spin| Clear {
+			lines,
+			push_down: 0,
+			t_spin,
+			perfect: false
+		};
+
+		let mut state = State::new();
+		assert_eq!(garbage_sent(&state, &clear(1, TSpin::None)), 0);
+		assert_eq!(garbage_sent(&state, &clear(2, TSpin::None)), 1);
+		assert_eq!(garbage_sent(&state, &clear(4, TSpin::None)), 4);
+		assert_eq!(garbage_sent(&state, &clear(2, TSpin::Full)), 4);
+		assert_eq!(garbage_sent(&state, &clear(0, TSpin::Full)), 0);
+
+		state.back_to_back = true;
+		state.combo = 3;
+		assert_eq!(garbage_sent(&state, &clear(4, TSpin::None)), 4 + 1 + 1);
+		assert_eq!(garbage_sent(&state, &clear(1, TSpin::None)), 1);
+	}
+}
@@ -1,9 +1,30 @@
-use std::cmp::max;
+use std::cmp::{max, min};
 use std::collections::HashSet;
-use std::collections::VecDeque;
+
+mod board;
+mod classify;
+mod features;
+mod fumen;
+mod garbage;
+mod movement;
+mod palette;
+mod randomizer;
+mod rotation;
+mod scoring;
+mod text;
+pub use board::*;
+pub use classify::*;
+pub use fumen::*;
+pub use garbage::*;
+pub use movement::*;
+pub use palette::*;
+pub use randomizer::*;
+pub use rotation::*;
+pub use scoring::*;
+pub use text::*;
 
 /// The shape a tetromino can have.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum MinoShape {
 	T,
 	J,
@@ -15,15 +36,33 @@ pub enum MinoShape {
 }
 
 impl MinoShape {
-	pub fn n_rotations(&self) -> i32 {
-		match self {
-			MinoShape::T => 4,
-			MinoShape::J => 4,
-			MinoShape::Z => 2,
-			MinoShape::O => 1,
-			MinoShape::S => 2,
-			MinoShape::L => 4,
-			MinoShape::I => 2
+	/// Every shape, in the order of NES Tetris's spawn table.
+	pub const ALL: [MinoShape; 7] = [
+		MinoShape::T,
+		MinoShape::J,
+		MinoShape::Z,
+		MinoShape::O,
+		MinoShape::S,
+		MinoShape::L,
+		MinoShape::I
+	];
+
+	/// Get this shape's position in `MinoShape::ALL`.
+	pub fn index(self) -> usize {
+		self as usize
+	}
+
+	/// Get the shape named by its uppercase letter, e.g. 'T'.
+	pub fn from_char(c: char) -> Option<MinoShape> {
+		match c {
+			'T' => Some(MinoShape::T),
+			'J' => Some(MinoShape::J),
+			'Z' => Some(MinoShape::Z),
+			'O' => Some(MinoShape::O),
+			'S' => Some(MinoShape::S),
+			'L' => Some(MinoShape::L),
+			'I' => Some(MinoShape::I),
+			_ => None
 		}
 	}
 }
@@ -34,12 +73,14 @@ pub struct Mino {
 	shape: MinoShape,
 	rot: i32,
 	x: i32,
-	y: i32
+	y: i32,
+	// Cell offsets for each rotation, from the mino's rotation system
+	cells: &'static Cells
 }
 
 impl Mino {
 	/// Create a mino with shape `shape`.
-	/// Gives it default spawn rotation and position.
+	/// Gives it default (NES) spawn rotation and position.
 	pub fn new(shape: MinoShape) -> Mino {
 		let rot = match shape {
 			MinoShape::T => 2,
@@ -51,46 +92,24 @@ impl Mino {
 			MinoShape::I => 1
 		};
 
+		Mino::with_cells(shape, nes_cells(shape), rot, 5, 0)
+	}
+
+	/// Create a mino with shape `shape` at (x, y) in rotation `rot`, where
+	/// `cells` gives the offsets of its cells in each rotation.
+	pub fn with_cells(shape: MinoShape, cells: &'static Cells, rot: i32, x: i32, y: i32) -> Mino {
 		Mino {
 			shape,
 			rot,
-			x: 5,
-			y: 0
+			x,
+			y,
+			cells
 		}
 	}
 
 	/// Get the points corresponding to this mino.
 	pub fn points(&self) -> [(i32, i32); 4] {
-		let deltas = match (self.shape, self.rot) {
-			// Thanks to meatfighter.com for the table
-			(MinoShape::T, 0) => [(-1, 0), (0, 0), (1, 0), (0, -1)],
-			(MinoShape::T, 1) => [(0, -1), (0, 0), (1, 0), (0, 1)],
-			(MinoShape::T, 2) => [(-1, 0), (0, 0), (1, 0), (0, 1)],
-			(MinoShape::T, 3) => [(0, -1), (-1, 0), (0, 0), (0, 1)],
-
-			(MinoShape::J, 0) => [(0, -1), (0, 0), (-1, 1), (0, 1)],
-			(MinoShape::J, 1) => [(-1, -1), (-1, 0), (0, 0), (1, 0)],
-			(MinoShape::J, 2) => [(0, -1), (1, -1), (0, 0), (0, 1)],
-			(MinoShape::J, 3) => [(-1, 0), (0, 0), (1, 0), (1, 1)],
-
-			(MinoShape::Z, 0) => [(-1, 0), (0, 0), (0, 1), (1, 1)],
-			(MinoShape::Z, 1) => [(1, -1), (0, 0), (1, 0), (0, 1)],
-
-			(MinoShape::O, 0) => [(-1, 0), (0, 0), (-1, 1), (0, 1)],
-
-			(MinoShape::S, 0) => [(0, 0), (1, 0), (-1, 1), (0, 1)],
-			(MinoShape::S, 1) => [(0, -1), (0, 0), (1, 0), (1, 1)],
-			
-			(MinoShape::L, 0) => [(0, -1), (0, 0), (0, 1), (1, 1)],
-			(MinoShape::L, 1) => [(-1, 0), (0, 0), (1, 0), (-1, 1)],
-			(MinoShape::L, 2) => [(-1, -1), (0, -1), (0, 0), (0, 1)],
-			(MinoShape::L, 3) => [(1, -1), (-1, 0), (0, 0), (1, 0)],
-			
-			(MinoShape::I, 0) => [(0, -2), (0, -1), (0, 0), (0, 1)],
-			(MinoShape::I, 1) => [(-2, 0), (-1, 0), (0, 0), (1, 0)],
-
-			(s, r) => panic!("Attempted to get points for nonexistent mino rotation ({:?}, {})", s, r)
-		};
+		let deltas = self.cells[self.rot as usize];
 
 		// lol no array map :(
 		let mut res = [(0, 0); 4];
@@ -104,7 +123,7 @@ impl Mino {
 
 	/// Produce a mino that has been rotated `n` times clockwise.
 	pub fn rotated(&self, n: i32) -> Mino {
-		let r = self.shape.n_rotations();
+		let r = self.cells.len() as i32;
 		// Have to convert n to a positive number first
 		let n = (n % r) + r;
 
@@ -123,66 +142,167 @@ impl Mino {
 			..*self
 		}
 	}
+
+	/// Get the shape of this mino.
+	pub fn shape(&self) -> MinoShape {
+		self.shape
+	}
+
+	/// Get the rotation index of this mino.
+	pub fn rot(&self) -> i32 {
+		self.rot
+	}
+
+	/// Get the x-coordinate of this mino's center.
+	pub fn x(&self) -> i32 {
+		self.x
+	}
+
+	/// Get the y-coordinate of this mino's center.
+	pub fn y(&self) -> i32 {
+		self.y
+	}
 }
 
-/// Standard 20x10 playing field.
-#[derive(Clone, Copy)]
-pub struct Board {
-	pub grid: [[bool; 10]; 20]
+/// A single input the player can make while maneuvering a mino.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Move {
+	Left,
+	Right,
+	Down,
+	Clockwise,
+	CounterClockwise
 }
 
-impl Board {
-	fn blank() -> Board {
-		Board {
-			grid: [[false; 10]; 20]
+impl Move {
+	/// Every move, in the order that searches try them.
+	pub const ALL: [Move; 5] = [
+		Move::Clockwise,
+		Move::CounterClockwise,
+		Move::Left,
+		Move::Right,
+		Move::Down
+	];
+
+	/// Produce the mino that results from making this move with `mino`.
+	pub fn apply(&self, mino: Mino) -> Mino {
+		match self {
+			Move::Left => mino.translated(-1, 0),
+			Move::Right => mino.translated(1, 0),
+			Move::Down => mino.translated(0, 1),
+			Move::Clockwise => mino.rotated(1),
+			Move::CounterClockwise => mino.rotated(-1)
 		}
 	}
+}
 
-	fn can_place(&self, mino: Mino) -> bool {
-		mino.points().iter()
-			.all(|(x, y)| {
-				let (x, y) = (*x, *y);
-				// The x-coord must be in bounds, but points may be above the grid
-				(0 <= x && x < 10) && (y < 20) && (y < 0 || !self.grid[y as usize][x as usize])
-			})
+/// A possible future state, along with the mino that causes it and the
+/// shortest sequence of moves that takes the mino there from its spawn.
+/// 
+/// `held` is set if the current piece went into the hold slot first, and
+/// `mino` is the piece that came out instead. `kind` says how the path got the
+/// mino into place (see `State::classify`).
+#[derive(Clone, Debug)]
+pub struct Placement<const W: usize = 10, const H: usize = 20> {
+	pub state: State<W, H>,
+	pub mino: Mino,
+	pub path: Vec<Move>,
+	pub held: bool,
+	pub kind: PlacementKind
+}
+
+/// Level at which NES Tetris's gravity reaches one row per frame, which is as
+/// far as most games go.
+pub const KILL_SCREEN: i32 = 29;
+
+/// Get the number of lines that must be cleared before NES Tetris leaves the
+/// `start` level for the first time.
+/// 
+/// Starting at level 9 or below takes 10 lines per level, as if the game had
+/// started at 0. Starting higher takes 100 lines, or for levels 16 and up
+/// somewhat more - from level 19 it takes 140.
+pub fn first_transition(start: i32) -> i32 {
+	min(start*10 + 10, max(100, start*10 - 50))
+}
+
+/// Get the level that a game started at level `start` is at after clearing
+/// `lines` lines. After the first transition, the level goes up every 10
+/// lines.
+pub fn level_after(start: i32, lines: i32) -> i32 {
+	let first = first_transition(start);
+	if lines < first {
+		start
+	}
+	else {
+		start + 1 + (lines - first)/10
 	}
 }
 
-#[cfg(test)]
-mod board_tests {
+/// The reason a game ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameOutcome {
+	/// The next piece overlapped the stack when it spawned.
+	ToppedOut,
+	/// The level reached `KILL_SCREEN`.
+	KillScreen,
+	/// The line cap set for the game was reached.
+	LineCap
 }
 
 /// Slice of the game frozen in time, and the primary interface through which
 /// games can be simulated.
 /// 
 /// Has implementations for different methods of accessing/viewing the board
-/// data which should aid in writing bots.
-#[derive(Clone, Copy)]
-pub struct State {
-	pub board: Board,
+/// data which should aid in writing bots. The board is `W` cells wide and `H`
+/// cells tall, 20x10 unless otherwise specified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct State<const W: usize = 10, const H: usize = 20> {
+	pub board: Board<W, H>,
 	pub score: i32,
 	pub level: i32,
-	pub lines: i32
+	pub lines: i32,
+	pub start: i32,
+	/// Number of placements in a row that have cleared lines.
+	pub combo: i32,
+	/// Whether the last placement to clear lines was a Tetris or T-spin.
+	pub back_to_back: bool,
+	/// Piece in the hold slot, for rule sets that have one.
+	pub hold: Option<MinoShape>
+}
+
+impl<const W: usize, const H: usize> Default for State<W, H> {
+	fn default() -> State<W, H> {
+		State::with_board(Board::blank(), 0)
+	}
 }
 
 impl State {
-	/// Construct a brand new state - blank board, level 0.
+	/// Construct a brand new state - blank 20x10 board, level 0.
 	pub fn new() -> State {
-		State {
-			board: Board::blank(),
-			score: 0,
-			level: 0,
-			lines: 0
-		}
+		State::with_start(0)
 	}
 
-	/// Construct a brand new state starting at a certain level.
+	/// Construct a brand new state with a blank 20x10 board, starting at a
+	/// certain level.
 	pub fn with_start(level: i32) -> State {
+		State::with_board(Board::blank(), level)
+	}
+}
+
+impl<const W: usize, const H: usize> State<W, H> {
+	/// Construct a brand new state with `board`, starting at a certain level.
+	/// Use this for boards that aren't 20x10, e.g.
+	/// `State::with_board(Board::<4, 20>::blank(), 0)`.
+	pub fn with_board(board: Board<W, H>, level: i32) -> State<W, H> {
 		State {
-			board: Board::blank(),
+			board,
 			score: 0,
 			level,
-			lines: 0
+			lines: 0,
+			start: level,
+			combo: 0,
+			back_to_back: false,
+			hold: None
 		}
 	}
 
@@ -191,65 +311,57 @@ impl State {
 	/// 
 	/// The resulting state is wrapped in `Option` because `mino` may be
 	/// unplaceable.
-	pub fn place(&self, mino: Mino) -> Option<State> {
+	pub fn place(&self, mino: Mino) -> Option<State<W, H>> {
+		self.place_with_push_down(mino, 0)
+	}
+
+	/// Produce a new state by placing `mino` on the board after it was soft
+	/// dropped (pushed down) for its last `push_down` rows, which earns
+	/// push-down points on top of the usual line clear points.
+	pub fn place_with_push_down(&self, mino: Mino, push_down: i32) -> Option<State<W, H>> {
+		self.place_scored(mino, push_down, TSpin::None, &NesScoring)
+	}
+
+	/// Produce a new state by placing `mino` on the board, scoring it with
+	/// `scoring`. The mino was pushed down for its last `push_down` rows and
+	/// got into place with `t_spin`.
+	pub fn place_scored<S: ScoringRule>(&self, mino: Mino, push_down: i32, t_spin: TSpin, scoring: &S) -> Option<State<W, H>> {
 		if !self.board.can_place(mino) {
 			return None;
 		}
 
 		let mut board = self.board;
-		let mut modified = HashSet::new();
-		for (x, y) in mino.points().iter() {
-			let (x, y) = (*x, *y);
-			if y >= 0 {
-				// Already called `can_place` so we won't go OOB here
-				board.grid[y as usize][x as usize] = true;
-				modified.insert(y);
-			}
-		}
-
-		let mut cleared = HashSet::new();
-		for y in modified.into_iter() {
-			if board.grid[y as usize].iter().all(|b| *b == true) {
-				cleared.insert(y);
-			}
-		}
-
-		let mut d = 0;
-		for y in (0..20).rev() {
-			while cleared.contains(&(y - d)) {
-				d += 1;
-			}
-
-			if y - d < 0 {
-				board.grid[y as usize] = [false; 10];
-			}
-			else {
-				board.grid[y as usize] = board.grid[(y - d) as usize];
-			}
-		}
+		let n_cleared = board.place(mino);
 
-		let n_cleared = cleared.len() as i32;
-		let score = self.score + match n_cleared {
-			0 => 0,
-			1 => 40*(self.level+1),
-			2 => 100*(self.level+1),
-			3 => 300*(self.level+1),
-			4 => 1200*(self.level+1),
-			n => panic!("Cleared too many ({}) lines in one turn", n)
+		let clear = Clear {
+			lines: n_cleared,
+			push_down,
+			t_spin,
+			perfect: n_cleared > 0 && board.is_empty()
 		};
+		let score = scoring.score(self, &clear);
 		let lines = self.lines + n_cleared;
-		let level = max(self.level, lines/10);
+		let level = level_after(self.start, lines);
+		let (combo, back_to_back) = if n_cleared > 0 {
+			(self.combo + 1, clear.is_difficult())
+		}
+		else {
+			(0, self.back_to_back)
+		};
 
 		Some(State {
 			board,
 			score,
 			level,
-			lines
+			lines,
+			combo,
+			back_to_back,
+			..*self
 		})
 	}
 
 	/// Produce a new state by dropping `mino` onto the board.
-	pub fn drop(&self, mino: Mino) -> Option<State> {
+	pub fn drop(&self, mino: Mino) -> Option<State<W, H>> {
 		let mut mino = mino;
 		while self.board.can_place(mino) {
 			mino = mino.translated(0, 1);
@@ -259,19 +371,47 @@ impl State {
 		self.place(mino)
 	}
 
+	/// Check whether `shape` can spawn, according to `rotation`, without
+	/// overlapping the stack.
+	pub fn can_spawn<R: RotationSystem>(&self, shape: MinoShape, rotation: &R) -> bool {
+		self.board.can_place(self.board.spawn(rotation, shape))
+	}
+
+	/// Check whether the game is over now that `next` is about to spawn
+	/// according to `rotation`, and if so, why. Games with a `line_cap` also
+	/// end once that many lines have been cleared.
+	pub fn outcome<R: RotationSystem>(&self, next: MinoShape, rotation: &R, line_cap: Option<i32>) -> Option<GameOutcome> {
+		if !self.can_spawn(next, rotation) {
+			Some(GameOutcome::ToppedOut)
+		}
+		else if self.level >= KILL_SCREEN {
+			Some(GameOutcome::KillScreen)
+		}
+		else if line_cap.is_some_and(|cap| self.lines >= cap) {
+			Some(GameOutcome::LineCap)
+		}
+		else {
+			None
+		}
+	}
+
 	/// Get row `y` from the board.
-	pub fn row(&self, y: usize) -> [bool; 10] {
-		self.board.grid[y]
+	pub fn row(&self, y: usize) -> [bool; W] {
+		let mut row = [false; W];
+
+		for (x, cell) in row.iter_mut().enumerate() {
+			*cell = self.board.get(x, y);
+		}
+
+		row
 	}
 
 	/// Get column `x` from the board.
-	pub fn column(&self, x: usize) -> [bool; 20] {
-		let mut col = [false; 20];
+	pub fn column(&self, x: usize) -> [bool; H] {
+		let mut col = [false; H];
 
-		for y in 0..20 {
-			if self.board.grid[y][x] {
-				col[y] = true;
-			}
+		for (y, cell) in col.iter_mut().enumerate() {
+			*cell = self.board.get(x, y);
 		}
 
 		col
@@ -279,83 +419,400 @@ impl State {
 
 	/// Get cell (x, y) from the board.
 	pub fn cell(&self, x: usize, y: usize) -> bool {
-		self.board.grid[y][x]
+		self.board.get(x, y)
 	}
 
 	/// Get the "depth" (number of consecutive empty cells from the top) of
 	/// column `x`.
 	pub fn column_depth(&self, x: usize) -> usize {
-		let col = self.column(x);
-		for (y, cell) in col.iter().enumerate() {
-			if *cell {
-				return y;
-			}
-		}
+		self.board.column_depth(x)
+	}
 
-		20
+	/// Get a hash of the board and hold slot, for transposition tables. It's
+	/// the board's Zobrist hash (see `Board::zobrist`), with the held piece
+	/// mixed in.
+	pub fn zobrist(&self) -> u64 {
+		let hold = match self.hold {
+			Some(shape) => shape.index() as u64 + 1,
+			None => 0
+		};
+
+		self.board.zobrist() ^ hold.wrapping_mul(0x9E37_79B9_7F4A_7C15)
 	}
 
-	/// Get all possible future board states and the minos that cause them.
-	pub fn possibilities(&self, next: MinoShape) -> Vec<(State, Mino)> {
+	/// Get all possible future board states and the minos that cause them,
+	/// with minos spawning and rotating according to `rotation`. Comes in the
+	/// same order as `placements`, with the same deduplication.
+	pub fn possibilities<R: RotationSystem>(&self, next: MinoShape, rotation: &R) -> Vec<(State<W, H>, Mino)> {
+		self.placements(next, rotation, &NesScoring).into_iter()
+			.map(|placement| (placement.state, placement.mino))
+			.collect()
+	}
+
+	/// Get all possible placements of `next`, each with the shortest path of
+	/// moves that reaches it from the spawn position. Minos spawn and rotate
+	/// according to `rotation`.
+	/// 
+	/// The player is assumed to soft drop through the trailing `Move::Down`s
+	/// of each path, and resulting states are scored with `scoring`.
+	///
+	/// Minos that land on the same cells from different rotations (e.g. the
+	/// two flat orientations of an SRS I) only show up once, as whichever has
+	/// the shortest path. Placements are sorted by their cells, left to right,
+	/// so the order doesn't depend on how the search went.
+	pub fn placements<R, S>(&self, next: MinoShape, rotation: &R, scoring: &S) -> Vec<Placement<W, H>>
+		where R: RotationSystem, S: ScoringRule
+	{
 		// Algorithm is a simple BFS, moving minos one unit/rotation at a time
+		// and remembering the move that first reached each (x, y, rot)
 
-		// Visited state for minos - visited[x][y][rot]
-		let mut visited = [[[false; 4]; 20]; 10];
+		let mut visited = vec![false; positions(W, H)];
 
-		let mut queue = VecDeque::new();
-		queue.push_back(Mino::new(next));
+		// Every mino reached so far, with the index of its parent and the
+		// move that got it here
+		let mut nodes: Vec<(Mino, Option<(usize, Move)>)> = Vec::new();
 
-		let mut minos = Vec::new();
+		let spawn = self.board.spawn(rotation, next);
+		if self.board.can_place(spawn) {
+			visited[position(spawn, H)] = true;
+			nodes.push((spawn, None));
+		}
 
-		while !queue.is_empty() {
-			let Mino { x, y, rot, .. } = queue.pop_front().unwrap();
-			let mino = Mino { x, y, rot, shape: next };
+		let mut placements = Vec::new();
+		let mut seen = HashSet::new();
 
-			if !self.board.can_place(mino) {
-				continue;
-			}
+		let mut i = 0;
+		while i < nodes.len() {
+			let (mino, _) = nodes[i];
 
-			let (xi, yi, roti) = (x as usize, y as usize, rot as usize);
-			if visited[xi][yi][roti] {
-				continue;
-			}
-			visited[xi][yi][roti] = true;
+			for m in Move::ALL.iter() {
+				let moved = match rotation.apply(&self.board, mino, *m) {
+					// Kicks could climb forever, so stop them at the ceiling
+					Some(moved) if moved.y >= CEILING => moved,
+					_ => { continue; }
+				};
 
-			queue.push_back(mino.translated(0, 1));
-			queue.push_back(mino.translated(-1, 0));
-			queue.push_back(mino.translated(1, 0));
-			queue.push_back(mino.rotated(-1));
-			queue.push_back(mino.rotated(1));
+				if visited[position(moved, H)] {
+					continue;
+				}
+				visited[position(moved, H)] = true;
+
+				nodes.push((moved, Some((i, *m))));
+			}
 
 			let down = mino.translated(0, 1);
-			if !self.board.can_place(down) {
-				minos.push(mino);
+			if !self.board.can_place(down) && seen.insert(footprint(mino)) {
+				let mut path = Vec::new();
+				let mut j = i;
+				while let (_, Some((parent, m))) = nodes[j] {
+					path.push(m);
+					j = parent;
+				}
+				path.reverse();
+
+				// Soft drop the last stretch straight down
+				let push_down = path.iter().rev()
+					.take_while(|m| **m == Move::Down)
+					.count() as i32;
+
+				let kind = self.classify(mino, &path, rotation);
+
+				placements.push(Placement {
+					state: self.place_scored(mino, push_down, kind.t_spin(), scoring).unwrap(),
+					mino,
+					path,
+					held: false,
+					kind
+				});
 			}
+
+			i += 1;
 		}
 
-		minos.into_iter()
-			.map(|mino| (self.place(mino).unwrap(), mino))
-			.collect()
+		placements.sort_by_key(|placement| footprint(placement.mino));
+		placements
+	}
+
+	/// Put `current` into the hold slot, producing the state with the updated
+	/// slot and the piece to play instead. That's the piece that was held, or
+	/// `next` if the slot was empty - in which case `next` is used up.
+	pub fn hold(&self, current: MinoShape, next: MinoShape) -> (State<W, H>, MinoShape) {
+		let state = State {
+			hold: Some(current),
+			..*self
+		};
+
+		(state, self.hold.unwrap_or(next))
+	}
+
+	/// Get all possible placements for a turn where the player may use the
+	/// hold slot once: placements of `current`, plus placements of whatever
+	/// comes out of the hold slot after holding `current` (see `hold`).
+	pub fn hold_placements<R, S>(&self, current: MinoShape, next: MinoShape, rotation: &R, scoring: &S) -> Vec<Placement<W, H>>
+		where R: RotationSystem, S: ScoringRule
+	{
+		self.hold_placements_with(current, next, |state, shape| state.placements(shape, rotation, scoring))
+	}
+
+	/// Like `hold_placements`, but with `generate` giving the placements of a
+	/// shape from a state (e.g. `reachable` instead of `placements`).
+	pub fn hold_placements_with<F>(&self, current: MinoShape, next: MinoShape, mut generate: F) -> Vec<Placement<W, H>>
+		where F: FnMut(&State<W, H>, MinoShape) -> Vec<Placement<W, H>>
+	{
+		let mut placements = generate(self, current);
+
+		// Swapping a piece for itself changes nothing
+		if self.hold != Some(current) {
+			let (held, shape) = self.hold(current, next);
+			let swapped = generate(&held, shape).into_iter()
+				.map(|placement| Placement { held: true, ..placement });
+			placements.extend(swapped);
+		}
+
+		placements
 	}
 }
 
+/// Highest a mino's center can go during a search. Spawning happens at row 0,
+/// but kicks can push minos up a little.
+const CEILING: i32 = -2;
+
+/// Get the number of distinct (x, y, rot) positions a mino can have during a
+/// search on a `width` by `height` board, leaving room for centers a couple of
+/// cells outside the board.
+fn positions(width: usize, height: usize) -> usize {
+	(width + 4) * (height + 2) * 4
+}
+
+/// Get the index of `mino`'s position on a board `height` rows tall, for
+/// tables of size `positions`.
+fn position(mino: Mino, height: usize) -> usize {
+	(((mino.x + 2) * (height as i32 + 2) + (mino.y - CEILING)) * 4 + mino.rot) as usize
+}
+
+/// Get the cells `mino` covers in sorted order, so that minos covering the
+/// same cells from different rotations compare equal.
+fn footprint(mino: Mino) -> [(i32, i32); 4] {
+	let mut points = mino.points();
+	points.sort_unstable();
+	points
+}
+
 #[cfg(test)]
 mod state_tests {
 	use crate::*;
 
+	#[test]
+	fn shape_indices() {
+		for (i, shape) in MinoShape::ALL.iter().enumerate() {
+			assert_eq!(shape.index(), i);
+		}
+	}
+
 	#[test]
 	fn possibilities() {
 		let state = State::new();
 		let next = MinoShape::J;
-		let possibilities = state.possibilities(next);
+		let possibilities = state.possibilities(next, &NesRotation);
 		assert_eq!(possibilities.len(), 34);
 
 		let state = State::new();
 		let mino = Mino::new(MinoShape::S).translated(0, 18);
 		let state = state.place(mino).unwrap();
 		let next = MinoShape::J;
-		let possibilities = state.possibilities(next);
+		let possibilities = state.possibilities(next, &NesRotation);
 		// 35 possibilities now that there's a tuck
 		assert_eq!(possibilities.len(), 35);
+
+		// Same order however the search reaches them: left to right
+		let columns: Vec<i32> = possibilities.iter()
+			.map(|(_, mino)| mino.points().iter().map(|(x, _)| *x).min().unwrap())
+			.collect();
+		assert!(columns.windows(2).all(|pair| pair[0] <= pair[1]));
+		let again = state.possibilities(next, &NesRotation);
+		assert!(possibilities.iter().zip(again.iter()).all(|(a, b)| a.1.points() == b.1.points()));
+	}
+
+	#[test]
+	fn board_sizes() {
+		// Modern 22-row visible area has the same placements, just lower
+		let state = State::with_board(Board::<10, 22>::blank(), 0);
+		let possibilities = state.possibilities(MinoShape::J, &NesRotation);
+		assert_eq!(possibilities.len(), 34);
+		assert!(possibilities.iter().all(|(_, mino)| mino.y >= 20));
+
+		// 4-wide: every flat I clears a line, and T has 2 + 3 + 2 + 3 spots
+		let state = State::with_board(Board::<4, 20>::blank(), 0);
+		let possibilities = state.possibilities(MinoShape::I, &NesRotation);
+		assert_eq!(possibilities.len(), 5);
+		assert_eq!(possibilities.iter().filter(|(state, _)| state.lines == 1).count(), 1);
+		assert_eq!(state.possibilities(MinoShape::T, &SrsRotation).len(), 10);
+		assert_eq!(state.row(0).len(), 4);
+		assert_eq!(state.column(0).len(), 20);
+	}
+
+	#[test]
+	fn level_progression() {
+		// (start, lines to first transition)
+		let starts = [(0, 10), (9, 100), (15, 100), (18, 130), (19, 140)];
+		for (start, first) in starts.iter() {
+			let (start, first) = (*start, *first);
+			assert_eq!(first_transition(start), first);
+			assert_eq!(level_after(start, 0), start);
+			assert_eq!(level_after(start, first - 1), start);
+			assert_eq!(level_after(start, first), start + 1);
+			assert_eq!(level_after(start, first + 9), start + 1);
+			assert_eq!(level_after(start, first + 10), start + 2);
+		}
+
+		// Clear a line at 139 lines on a 19 start to go to level 20
+		let mut state = State::with_start(19);
+		state.lines = 139;
+		for x in 0..9 {
+			state.board.set(x, 19, true);
+		}
+		let mino = Mino::new(MinoShape::I).rotated(1).translated(4, 18);
+		let state = state.place(mino).unwrap();
+		assert_eq!((state.start, state.level, state.lines), (19, 20, 140));
+		assert_eq!(state.score, 40*20);
+	}
+
+	#[test]
+	fn push_down_points() {
+		let mino = Mino::new(MinoShape::O).translated(0, 18);
+
+		let state = State::new().place_with_push_down(mino, 10).unwrap();
+		assert_eq!(state.score, 10);
+
+		// No points for locking without pushing down
+		let state = State::new().place_with_push_down(mino, 0).unwrap();
+		assert_eq!(state.score, 0);
+
+		// The tens digit carries and the cartridge loses the ones digit
+		let mut state = State::new();
+		state.score = 95;
+		let state = state.place_with_push_down(mino, 10).unwrap();
+		assert_eq!(state.score, 100);
+
+		// Hard-dropping an O from spawn pushes down all 18 rows, but the
+		// binary 19 gets added to the BCD score as if it were 13
+		let placement = State::new().placements(MinoShape::O, &NesRotation, &NesScoring).into_iter()
+			.find(|placement| placement.mino.x == 5)
+			.unwrap();
+		assert_eq!(placement.state.score, 12);
+	}
+
+	#[test]
+	fn combos() {
+		// Two singles in a row, then nothing
+		let mut state = State::new();
+		for x in 0..9 {
+			state.board.set(x, 19, true);
+		}
+		for x in 0..8 {
+			state.board.set(x, 18, true);
+		}
+		let mino = Mino::new(MinoShape::I).rotated(1).translated(4, 18);
+		let state = state.place_scored(mino, 0, TSpin::None, &GuidelineScoring).unwrap();
+		assert_eq!((state.combo, state.score), (1, 100));
+		let mino = Mino::new(MinoShape::I).rotated(1).translated(3, 18);
+		let state = state.place_scored(mino, 0, TSpin::None, &GuidelineScoring).unwrap();
+		assert_eq!((state.combo, state.score), (2, 100 + 100 + 50));
+		let mino = Mino::new(MinoShape::O).translated(-4, 18);
+		let state = state.place_scored(mino, 0, TSpin::None, &GuidelineScoring).unwrap();
+		assert_eq!((state.combo, state.back_to_back), (0, false));
+	}
+
+	#[test]
+	fn max_score() {
+		let mut state = State::with_start(19);
+		state.score = 999000;
+		for x in 0..9 {
+			for y in 16..20 {
+				state.board.set(x, y, true);
+			}
+		}
+		let mino = Mino::new(MinoShape::I).rotated(1).translated(4, 18);
+		let state = state.place(mino).unwrap();
+		assert_eq!(state.score, MAX_SCORE);
+	}
+
+	#[test]
+	fn outcome() {
+		let state = State::new();
+		assert_eq!(state.outcome(MinoShape::T, &NesRotation, None), None);
+
+		// Column stacked all the way to the top
+		let mut state = State::new();
+		for y in 0..20 {
+			state.board.set(5, y, true);
+		}
+		assert!(!state.can_spawn(MinoShape::I, &NesRotation));
+		assert_eq!(state.outcome(MinoShape::I, &NesRotation, None), Some(GameOutcome::ToppedOut));
+
+		// A flat NES T covers (6, 0), but an SRS T spawns pointing up, a
+		// column further left
+		let mut state = State::new();
+		state.board.set(6, 0, true);
+		assert_eq!(state.outcome(MinoShape::T, &NesRotation, None), Some(GameOutcome::ToppedOut));
+		assert_eq!(state.outcome(MinoShape::T, &SrsRotation, None), None);
+
+		let state = State::with_start(KILL_SCREEN);
+		assert_eq!(state.outcome(MinoShape::T, &NesRotation, None), Some(GameOutcome::KillScreen));
+
+		let mut state = State::new();
+		state.lines = 300;
+		assert_eq!(state.outcome(MinoShape::T, &NesRotation, Some(300)), Some(GameOutcome::LineCap));
+		assert_eq!(state.outcome(MinoShape::T, &NesRotation, None), None);
+	}
+
+	#[test]
+	fn hold() {
+		let state = State::new();
+		let placements = state.hold_placements(MinoShape::O, MinoShape::I, &NesRotation, &NesScoring);
+		let (held, placed): (Vec<&Placement>, Vec<&Placement>) = placements.iter().partition(|p| p.held);
+		assert_eq!(placed.len(), 9);
+		assert_eq!(held.len(), 17);
+		assert!(held.iter().all(|p| p.mino.shape == MinoShape::I && p.state.hold == Some(MinoShape::O)));
+
+		// Holding again swaps back
+		let state = held[0].state;
+		let (state, shape) = state.hold(MinoShape::T, MinoShape::L);
+		assert_eq!((state.hold, shape), (Some(MinoShape::T), MinoShape::O));
+
+		// Nothing to gain from swapping a piece for itself
+		let placements = state.hold_placements(MinoShape::T, MinoShape::L, &NesRotation, &NesScoring);
+		assert!(placements.iter().all(|p| !p.held));
+
+		// Any placement generator can be used, like frame-accurate reachability
+		let state = State::new();
+		let placements = state.hold_placements_with(MinoShape::O, MinoShape::I, |state, shape| state.reachable(shape, Shifting::Das, &NesScoring));
+		assert_eq!(placements.iter().filter(|p| p.held).count(), 17);
+	}
+
+	#[test]
+	fn placement_paths() {
+		let state = State::new();
+		let mino = Mino::new(MinoShape::S).translated(0, 18);
+		let state = state.place(mino).unwrap();
+
+		for placement in state.placements(MinoShape::J, &NesRotation, &NesScoring) {
+			let mut mino = Mino::new(MinoShape::J);
+			for m in placement.path.iter() {
+				mino = m.apply(mino);
+				assert!(state.board.can_place(mino));
+			}
+
+			let Mino { x, y, rot, .. } = mino;
+			assert_eq!((x, y, rot), (placement.mino.x, placement.mino.y, placement.mino.rot));
+		}
+
+		// Flat J shifted to the left wall, then dropped
+		let drop = state.placements(MinoShape::J, &NesRotation, &NesScoring).into_iter()
+			.find(|placement| placement.mino.y == 18 && placement.mino.x == 1 && placement.mino.rot == 3)
+			.unwrap();
+		let mut path = vec![Move::Left; 4];
+		path.append(&mut vec![Move::Down; 18]);
+		assert_eq!(drop.path, path);
 	}
 }
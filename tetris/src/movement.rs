@@ -0,0 +1,338 @@
+use crate::*;
+
+/// Frames before delayed auto shift kicks in after a direction is pressed.
+pub const DAS_DELAY: i32 = 16;
+
+/// Frames between shifts once delayed auto shift has kicked in.
+pub const DAS_REPEAT: i32 = 6;
+
+/// Get the number of frames it takes a mino to fall one row at `level`, as in
+/// NTSC NES Tetris.
+pub fn gravity(level: i32) -> i32 {
+	match level {
+		0 => 48,
+		1 => 43,
+		2 => 38,
+		3 => 33,
+		4 => 28,
+		5 => 23,
+		6 => 18,
+		7 => 13,
+		8 => 8,
+		9 => 6,
+		10..=12 => 5,
+		13..=15 => 4,
+		16..=18 => 3,
+		19..=28 => 2,
+		_ => 1
+	}
+}
+
+/// How the player shifts minos horizontally.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shifting {
+	/// Hold the direction down and let delayed auto shift move the mino: one
+	/// shift on the press, one `DAS_DELAY` frames later, then one every
+	/// `DAS_REPEAT` frames. A new press can't come sooner than the next
+	/// automatic shift would have.
+	Das,
+	/// Tap the direction, at most once every `n` frames.
+	Tap(i32)
+}
+
+// Whether a direction is being held down, for `Shifting::Das`
+const RELEASED: usize = 0;
+const HELD_LEFT: usize = 1;
+const HELD_RIGHT: usize = 2;
+
+/// A mino in flight during a particular frame.
+#[derive(Clone, Copy)]
+struct Flight {
+	mino: Mino,
+	// Direction held, one of `RELEASED`, `HELD_LEFT` or `HELD_RIGHT`
+	held: usize,
+	// First frame on which the mino may shift again
+	ready: i32,
+	// Whether a rotation button was pressed on the previous frame
+	rotated: bool,
+	// Shift and rotation made this frame
+	inputs: Inputs,
+	// Index of the last frame with inputs in `nodes`
+	node: usize
+}
+
+/// The shift and rotation made during a single frame.
+type Inputs = [Option<Move>; 2];
+
+impl<const W: usize, const H: usize> State<W, H> {
+	/// Get the placements of `next` that a player shifting with `shifting`
+	/// can actually make in time at this state's level.
+	///
+	/// Runs the mino frame by frame: each frame the player may shift once and
+	/// rotate once (but can't rotate on two frames in a row, since the button
+	/// has to be released), then gravity pulls the mino down one row every
+	/// `gravity(level)` frames, locking it if it can't fall. Soft dropping is
+	/// not modeled, since falling sooner never makes a placement reachable.
+	///
+	/// Paths include a `Move::Down` for every row the mino falls, and the
+	/// moves made during a frame come before that frame's drop. As with
+	/// `placements`, the player is assumed to soft drop through the trailing
+	/// `Move::Down`s, and resulting states are scored with `scoring`.
+	pub fn reachable<S: ScoringRule>(&self, next: MinoShape, shifting: Shifting, scoring: &S) -> Vec<Placement<W, H>> {
+		let frames_per_row = gravity(self.level);
+
+		// Every frame in which the player made an input, as the mino after
+		// those inputs, the inputs themselves, and the previous such frame
+		let mut nodes: Vec<(Mino, Inputs, Option<usize>)> = Vec::new();
+
+		let spawn = self.board.spawn(&NesRotation, next);
+		if !self.board.can_place(spawn) {
+			return Vec::new();
+		}
+		nodes.push((spawn, [None, None], None));
+
+		let mut flights = vec![Flight {
+			mino: spawn,
+			held: RELEASED,
+			ready: 0,
+			rotated: false,
+			inputs: [None, None],
+			node: 0
+		}];
+
+		// Minos that have already locked - locked[x][y][rot]
+		let mut locked = [[[false; 4]; H]; W];
+		let mut placements = Vec::new();
+
+		let mut frame = 0;
+		while !flights.is_empty() {
+			let drops = (frame + 1) % frames_per_row == 0;
+
+			// Best flight for each (x, rot, held, rotated) next frame, where
+			// better means able to shift sooner
+			let mut best: [[[[Option<usize>; 2]; 3]; 4]; W] = [[[[None; 2]; 3]; 4]; W];
+			let mut next_flights: Vec<Flight> = Vec::new();
+
+			for flight in flights.iter() {
+				for (held, ready, shift) in shift_options(flight, shifting, frame).iter() {
+					let mut mino = flight.mino;
+
+					let (held, ready) = match shift {
+						Some(m) if self.board.can_place(m.apply(mino)) => {
+							mino = m.apply(mino);
+							(*held, *ready)
+						},
+						// Held shifts into a wall keep DAS charged
+						Some(_) if *held == flight.held => (*held, flight.ready),
+						Some(_) => { continue; },
+						None => (*held, *ready)
+					};
+					let shift = if mino.x == flight.mino.x { None } else { *shift };
+
+					for rotation in [None, Some(Move::Clockwise), Some(Move::CounterClockwise)].iter() {
+						let mut mino = mino;
+						if let Some(m) = rotation {
+							let rotated = m.apply(mino);
+							if flight.rotated || rotated.rot == mino.rot || !self.board.can_place(rotated) {
+								continue;
+							}
+							mino = rotated;
+						}
+
+						let mut flight = Flight {
+							mino,
+							held,
+							ready,
+							rotated: rotation.is_some(),
+							inputs: [shift, *rotation],
+							node: flight.node
+						};
+
+						// Apply gravity
+						if drops {
+							let down = flight.mino.translated(0, 1);
+							if !self.board.can_place(down) {
+								let Mino { x, y, rot, .. } = flight.mino;
+								let (xi, yi, roti) = (x as usize, y as usize, rot as usize);
+								if !locked[xi][yi][roti] {
+									locked[xi][yi][roti] = true;
+									if flight.inputs != [None, None] {
+										nodes.push((flight.mino, flight.inputs, Some(flight.node)));
+										flight.node = nodes.len() - 1;
+									}
+									let path = path(&nodes, flight.node, flight.mino);
+									let push_down = path.iter().rev()
+										.take_while(|m| **m == Move::Down)
+										.count() as i32;
+									let kind = self.classify(flight.mino, &path, &NesRotation);
+									placements.push(Placement {
+										state: self.place_scored(flight.mino, push_down, kind.t_spin(), scoring).unwrap(),
+										mino: flight.mino,
+										kind,
+										path,
+										held: false
+									});
+								}
+								continue;
+							}
+						}
+
+						let Mino { x, rot, .. } = flight.mino;
+						let slot = &mut best[x as usize][rot as usize][flight.held][flight.rotated as usize];
+						match slot {
+							Some(i) if next_flights[*i].ready <= flight.ready => {},
+							Some(i) => { next_flights[*i] = flight; },
+							None => {
+								*slot = Some(next_flights.len());
+								next_flights.push(flight);
+							}
+						}
+					}
+				}
+			}
+
+			// Remember the inputs of the flights that made it, then let them
+			// fall
+			for flight in next_flights.iter_mut() {
+				if flight.inputs != [None, None] {
+					nodes.push((flight.mino, flight.inputs, Some(flight.node)));
+					flight.node = nodes.len() - 1;
+					flight.inputs = [None, None];
+				}
+				if drops {
+					flight.mino = flight.mino.translated(0, 1);
+				}
+			}
+
+			flights = next_flights;
+			frame += 1;
+		}
+
+		placements
+	}
+}
+
+/// Get the ways the player can work the d-pad on `frame`, as the direction
+/// held and next ready frame afterwards plus the shift to attempt, if any.
+fn shift_options(flight: &Flight, shifting: Shifting, frame: i32) -> Vec<(usize, i32, Option<Move>)> {
+	let can_press = frame >= flight.ready;
+	let mut options = Vec::with_capacity(3);
+	options.push((RELEASED, flight.ready, None));
+
+	match shifting {
+		Shifting::Das => {
+			for (held, m) in [(HELD_LEFT, Move::Left), (HELD_RIGHT, Move::Right)].iter() {
+				if flight.held == *held {
+					// Keep holding, auto shifting when DAS is charged
+					if can_press {
+						options.push((*held, frame + DAS_REPEAT, Some(*m)));
+					}
+					else {
+						options.push((*held, flight.ready, None));
+					}
+				}
+				else if can_press {
+					options.push((*held, frame + DAS_DELAY, Some(*m)));
+				}
+			}
+		},
+		Shifting::Tap(n) => {
+			if can_press {
+				options.push((RELEASED, frame + n, Some(Move::Left)));
+				options.push((RELEASED, frame + n, Some(Move::Right)));
+			}
+		}
+	}
+
+	options
+}
+
+/// Rebuild the moves leading up to node `i`, then down to `end`.
+fn path(nodes: &[(Mino, Inputs, Option<usize>)], i: usize, end: Mino) -> Vec<Move> {
+	let mut frames = Vec::new();
+	let mut j = Some(i);
+	while let Some(k) = j {
+		frames.push(k);
+		j = nodes[k].2;
+	}
+	frames.reverse();
+
+	let mut path = Vec::new();
+	let mut y = 0;
+	for k in frames.into_iter() {
+		let (mino, moves, _) = &nodes[k];
+		for _ in y..mino.y {
+			path.push(Move::Down);
+		}
+		y = mino.y;
+		path.extend(moves.iter().flatten());
+	}
+	for _ in y..end.y {
+		path.push(Move::Down);
+	}
+
+	path
+}
+
+#[cfg(test)]
+mod movement_tests {
+	use crate::*;
+
+	#[test]
+	fn slow_levels_reach_everything() {
+		let state = State::new();
+		let all = state.placements(MinoShape::J, &NesRotation, &NesScoring).len();
+		assert_eq!(state.reachable(MinoShape::J, Shifting::Das, &NesScoring).len(), all);
+		assert_eq!(state.reachable(MinoShape::J, Shifting::Tap(4), &NesScoring).len(), all);
+	}
+
+	#[test]
+	fn kill_screen_limits_das() {
+		// At 1 frame per row, DAS only shifts twice before row 17
+		let state = State::with_start(29);
+		let placements = state.reachable(MinoShape::I, Shifting::Das, &NesScoring);
+		let columns: Vec<i32> = placements.iter()
+			.filter(|placement| placement.mino.rot == 1)
+			.map(|placement| placement.mino.x)
+			.collect();
+		assert!(columns.contains(&5));
+		assert!(!columns.contains(&2));
+		assert!(!columns.contains(&9));
+	}
+
+	#[test]
+	fn paths_follow_gravity() {
+		let state = State::with_start(19);
+		for placement in state.reachable(MinoShape::T, Shifting::Tap(3), &NesScoring) {
+			let mut mino = Mino::new(MinoShape::T);
+			for m in placement.path.iter() {
+				mino = m.apply(mino);
+				assert!(state.board.can_place(mino));
+			}
+			assert_eq!((mino.x, mino.y, mino.rot), (placement.mino.x, placement.mino.y, placement.mino.rot));
+
+			let drops = placement.path.iter().filter(|m| **m == Move::Down).count();
+			assert_eq!(drops as i32, placement.mino.y);
+		}
+	}
+
+	#[test]
+	fn push_down_points() {
+		// Scored like `placements`, soft dropping the last stretch
+		let state = State::new();
+		let placements = state.placements(MinoShape::J, &NesRotation, &NesScoring);
+		for placement in state.reachable(MinoShape::J, Shifting::Das, &NesScoring) {
+			let push_down = placement.clear(&state).push_down;
+			assert_eq!(placement.state, state.place_with_push_down(placement.mino, push_down).unwrap());
+
+			// Straight drops have the same path either way
+			if placement.path.iter().all(|m| *m == Move::Down) {
+				let same = placements.iter()
+					.find(|other| other.mino.points() == placement.mino.points())
+					.unwrap();
+				assert!(placement.state.score > 0);
+				assert_eq!(placement.state.score, same.state.score);
+			}
+		}
+	}
+}
@@ -0,0 +1,69 @@
+use crate::*;
+
+/// NES master palette index of the white that T, O and I blocks are filled
+/// with.
+const NES_WHITE: u8 = 0x30;
+
+/// The two colors of each level's palette as NES master palette indices:
+/// first the one for J and S (and the borders of T, O and I), then the one for
+/// Z and L. The cycle repeats every 10 levels.
+const NES_LEVEL_COLORS: [(u8, u8); 10] = [
+	(0x12, 0x21),
+	(0x1A, 0x29),
+	(0x14, 0x24),
+	(0x12, 0x2A),
+	(0x15, 0x2B),
+	(0x2B, 0x22),
+	(0x16, 0x00),
+	(0x13, 0x05),
+	(0x12, 0x16),
+	(0x16, 0x27)
+];
+
+/// How NES Tetris draws a block, as indices into the NES master palette.
+/// Renderers can look them up in whichever palette their emulator of choice
+/// uses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NesBlock {
+	pub fill: u8,
+	pub border: u8
+}
+
+/// Get how NES Tetris draws blocks of `shape` at `level`.
+pub fn nes_block(shape: MinoShape, level: i32) -> NesBlock {
+	let (primary, secondary) = NES_LEVEL_COLORS[level.rem_euclid(10) as usize];
+	match shape {
+		MinoShape::T | MinoShape::O | MinoShape::I => NesBlock { fill: NES_WHITE, border: primary },
+		MinoShape::J | MinoShape::S => NesBlock { fill: primary, border: primary },
+		MinoShape::Z | MinoShape::L => NesBlock { fill: secondary, border: secondary }
+	}
+}
+
+impl<const W: usize, const H: usize> State<W, H> {
+	/// Get how NES Tetris would draw cell (x, y) at this state's level, if
+	/// it's filled and its shape is known.
+	pub fn nes_block(&self, x: usize, y: usize) -> Option<NesBlock> {
+		self.board.shape(x, y).map(|shape| nes_block(shape, self.level))
+	}
+}
+
+#[cfg(test)]
+mod palette_tests {
+	use crate::*;
+
+	#[test]
+	fn level_colors() {
+		let t = nes_block(MinoShape::T, 0);
+		assert_eq!(t.fill, 0x30);
+		assert_eq!(nes_block(MinoShape::J, 0).fill, t.border);
+		assert_eq!(nes_block(MinoShape::L, 18), nes_block(MinoShape::Z, 8));
+		assert_eq!(nes_block(MinoShape::S, 29), nes_block(MinoShape::S, 9));
+
+		let mut state = State::with_start(6);
+		let mino = Mino::new(MinoShape::S).translated(0, 18);
+		state = state.place(mino).unwrap();
+		let (x, y) = mino.points()[0];
+		assert_eq!(state.nes_block(x as usize, y as usize), Some(NesBlock { fill: 0x16, border: 0x16 }));
+		assert_eq!(state.nes_block(0, 0), None);
+	}
+}
@@ -0,0 +1,500 @@
+use crate::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Source of the sequence of pieces in a game.
+pub trait Randomizer {
+	/// Produce the next piece.
+	fn next(&mut self) -> MinoShape;
+
+	/// Let the randomizer know that `frames` frames have gone by since the
+	/// last piece. Randomizers that don't depend on timing can ignore this.
+	fn elapse(&mut self, _frames: u32) {}
+
+	/// Get the chance of each piece (in `MinoShape::ALL` order) being the one
+	/// `next` produces. Unless overridden, every piece is equally likely.
+	fn probabilities(&self) -> [f64; 7] {
+		[1.0 / 7.0; 7]
+	}
+}
+
+/// Orientation IDs that NES Tetris spawns each shape with, in
+/// `MinoShape::ALL` order.
+const NES_SPAWN_TABLE: [u8; 7] = [0x02, 0x07, 0x08, 0x0A, 0x0B, 0x0E, 0x12];
+
+/// The piece generator from NES Tetris.
+///
+/// The game keeps a 16-bit LFSR that it steps once every frame. To pick a
+/// piece, it adds a running piece count to the high byte of the LFSR and takes
+/// the low 3 bits as an index into the spawn table. If that index is 7 or
+/// gives the same piece as last time, it steps the LFSR and rolls again
+/// (this time without the piece count) and takes whatever comes up.
+#[derive(Clone, Copy, Debug)]
+pub struct NesRandomizer {
+	seed: u16,
+	spawn_count: u8,
+	spawn_id: u8
+}
+
+impl NesRandomizer {
+	/// Seed the LFSR holds when the console is powered on.
+	pub const POWER_ON_SEED: u16 = 0x8988;
+
+	/// Construct a generator with LFSR value `seed`, before any piece has been
+	/// picked. The LFSR never leaves zero, so `seed` should be nonzero.
+	pub fn new(seed: u16) -> NesRandomizer {
+		NesRandomizer {
+			seed,
+			spawn_count: 0,
+			spawn_id: 0
+		}
+	}
+
+	/// Get the current value of the LFSR.
+	pub fn seed(&self) -> u16 {
+		self.seed
+	}
+
+	/// Step the LFSR once, as the game does every frame.
+	pub fn step(&mut self) {
+		let bit = ((self.seed >> 9) ^ (self.seed >> 1)) & 1;
+		self.seed = (bit << 15) | (self.seed >> 1);
+	}
+}
+
+impl Randomizer for NesRandomizer {
+	fn next(&mut self) -> MinoShape {
+		self.spawn_count = self.spawn_count.wrapping_add(1);
+
+		let index = ((self.seed >> 8) as u8).wrapping_add(self.spawn_count) & 7;
+		if index != 7 && NES_SPAWN_TABLE[index as usize] != self.spawn_id {
+			self.spawn_id = NES_SPAWN_TABLE[index as usize];
+			return MinoShape::ALL[index as usize];
+		}
+
+		self.step();
+		let index = (((self.seed >> 8) as u8 & 7) + self.spawn_id) % 7;
+		self.spawn_id = NES_SPAWN_TABLE[index as usize];
+		MinoShape::ALL[index as usize]
+	}
+
+	fn elapse(&mut self, frames: u32) {
+		for _ in 0..frames {
+			self.step();
+		}
+	}
+
+	/// The LFSR steps every frame, so which piece comes out depends on exactly
+	/// when it's picked. Treating the LFSR as uniformly random instead, the
+	/// first roll is a fresh piece with chance 1/8 each. A reroll adds the
+	/// previous spawn ID (not its index) to a number from 0 to 7, so one piece
+	/// comes up twice as often as the rest.
+	fn probabilities(&self) -> [f64; 7] {
+		let previous = NES_SPAWN_TABLE.iter().position(|id| *id == self.spawn_id);
+
+		let mut probabilities = [0.0; 7];
+		let mut reroll = 1.0 / 8.0;
+		for (i, probability) in probabilities.iter_mut().enumerate() {
+			if Some(i) == previous {
+				reroll += 1.0 / 8.0;
+			}
+			else {
+				*probability += 1.0 / 8.0;
+			}
+		}
+
+		for r in 0..8 {
+			probabilities[(r + self.spawn_id as usize) % 7] += reroll / 8.0;
+		}
+
+		probabilities
+	}
+}
+
+/// Small xorshift generator, so that randomizers can be seeded and replayed
+/// without pulling in a dependency.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+	pub(crate) fn new(seed: u64) -> Rng {
+		// Xorshift gets stuck at zero, which the mixing gives for one seed
+		match seed ^ 0x9E37_79B9_7F4A_7C15 {
+			0 => Rng(0x2545_F491_4F6C_DD1D),
+			state => Rng(state)
+		}
+	}
+
+	/// Get a uniformly random number in `0..n`.
+	pub(crate) fn below(&mut self, n: usize) -> usize {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		(self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 32) as usize % n
+	}
+}
+
+/// Every piece is equally likely, no matter what came before.
+#[derive(Clone, Copy, Debug)]
+pub struct UniformRandomizer {
+	rng: Rng
+}
+
+impl UniformRandomizer {
+	pub fn new(seed: u64) -> UniformRandomizer {
+		UniformRandomizer {
+			rng: Rng::new(seed)
+		}
+	}
+}
+
+impl Randomizer for UniformRandomizer {
+	fn next(&mut self) -> MinoShape {
+		MinoShape::ALL[self.rng.below(7)]
+	}
+}
+
+/// The modern guideline generator: deals out shuffled bags holding one of each
+/// piece.
+#[derive(Clone, Copy, Debug)]
+pub struct BagRandomizer {
+	rng: Rng,
+	bag: [MinoShape; 7],
+	// Number of pieces already dealt from `bag`
+	dealt: usize
+}
+
+impl BagRandomizer {
+	pub fn new(seed: u64) -> BagRandomizer {
+		BagRandomizer {
+			rng: Rng::new(seed),
+			bag: MinoShape::ALL,
+			dealt: 7
+		}
+	}
+}
+
+impl Randomizer for BagRandomizer {
+	fn next(&mut self) -> MinoShape {
+		if self.dealt == 7 {
+			// Fisher-Yates
+			for i in (1..7).rev() {
+				let j = self.rng.below(i + 1);
+				self.bag.swap(i, j);
+			}
+			self.dealt = 0;
+		}
+
+		self.dealt += 1;
+		self.bag[self.dealt - 1]
+	}
+
+	/// Every piece left in the bag is equally likely, or any piece when a new
+	/// bag is about to start.
+	fn probabilities(&self) -> [f64; 7] {
+		if self.dealt == 7 {
+			return [1.0 / 7.0; 7];
+		}
+
+		let left = &self.bag[self.dealt..];
+		let mut probabilities = [0.0; 7];
+		for shape in left.iter() {
+			probabilities[shape.index()] = 1.0 / left.len() as f64;
+		}
+
+		probabilities
+	}
+}
+
+/// TGM-style generator: rolls up to `rolls` times for a piece that isn't one
+/// of the last four, settling for the last roll if every one was.
+#[derive(Clone, Copy, Debug)]
+pub struct HistoryRandomizer {
+	rng: Rng,
+	history: [MinoShape; 4],
+	rolls: u32,
+	first: bool
+}
+
+impl HistoryRandomizer {
+	/// Construct a generator with an initial history and number of rolls.
+	pub fn new(seed: u64, history: [MinoShape; 4], rolls: u32) -> HistoryRandomizer {
+		HistoryRandomizer {
+			rng: Rng::new(seed),
+			history,
+			rolls,
+			first: true
+		}
+	}
+
+	/// The Tetris: The Grand Master generator - 4 rolls, starting from a
+	/// history of all Zs.
+	pub fn tgm(seed: u64) -> HistoryRandomizer {
+		HistoryRandomizer::new(seed, [MinoShape::Z; 4], 4)
+	}
+
+	/// The Tetris: The Absolute generator - 6 rolls, starting from a history
+	/// of Z, S, S, Z.
+	pub fn tap(seed: u64) -> HistoryRandomizer {
+		HistoryRandomizer::new(seed, [MinoShape::Z, MinoShape::S, MinoShape::S, MinoShape::Z], 6)
+	}
+}
+
+impl Randomizer for HistoryRandomizer {
+	fn next(&mut self) -> MinoShape {
+		let shape = if self.first {
+			// The first piece is never an S, Z or O
+			self.first = false;
+			[MinoShape::T, MinoShape::J, MinoShape::L, MinoShape::I][self.rng.below(4)]
+		}
+		else {
+			let mut shape = MinoShape::ALL[self.rng.below(7)];
+			for _ in 1..self.rolls {
+				if !self.history.contains(&shape) {
+					break;
+				}
+				shape = MinoShape::ALL[self.rng.below(7)];
+			}
+
+			shape
+		};
+
+		self.history.rotate_right(1);
+		self.history[0] = shape;
+
+		shape
+	}
+
+	/// A piece outside the history comes up if it's rolled before any
+	/// piece outside the history is; a piece in the history only comes up if
+	/// every roll lands in the history and it's the last one.
+	fn probabilities(&self) -> [f64; 7] {
+		let mut probabilities = [0.0; 7];
+		if self.first {
+			for shape in [MinoShape::T, MinoShape::J, MinoShape::L, MinoShape::I].iter() {
+				probabilities[shape.index()] = 1.0 / 4.0;
+			}
+			return probabilities;
+		}
+
+		let repeat = MinoShape::ALL.iter()
+			.filter(|shape| self.history.contains(shape))
+			.count() as f64 / 7.0;
+		let rolls = self.rolls.max(1) as i32;
+
+		for (shape, probability) in MinoShape::ALL.iter().zip(probabilities.iter_mut()) {
+			*probability = if self.history.contains(shape) {
+				repeat.powi(rolls - 1) / 7.0
+			}
+			else {
+				(0..rolls).map(|i| repeat.powi(i)).sum::<f64>() / 7.0
+			};
+		}
+
+		probabilities
+	}
+}
+
+/// A fixed sequence of pieces, which repeats once it runs out.
+#[derive(Clone, Debug)]
+pub struct ScriptedRandomizer {
+	sequence: Vec<MinoShape>,
+	i: usize
+}
+
+impl ScriptedRandomizer {
+	/// Construct a randomizer that deals out `sequence` in order. Panics if
+	/// `sequence` is empty.
+	pub fn new(sequence: Vec<MinoShape>) -> ScriptedRandomizer {
+		assert!(!sequence.is_empty(), "Scripted randomizer needs at least one piece");
+
+		ScriptedRandomizer {
+			sequence,
+			i: 0
+		}
+	}
+
+	/// Read a sequence of piece letters (e.g. "TJZOSLI") from the file at
+	/// `path`. Whitespace between letters is ignored.
+	pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<ScriptedRandomizer> {
+		let text = fs::read_to_string(path)?;
+
+		let mut sequence = Vec::new();
+		for c in text.chars().filter(|c| !c.is_whitespace()) {
+			match MinoShape::from_char(c) {
+				Some(shape) => { sequence.push(shape); },
+				None => {
+					let message = format!("Unparseable mino type {} in piece sequence", c);
+					return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+				}
+			}
+		}
+
+		if sequence.is_empty() {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "Empty piece sequence"));
+		}
+
+		Ok(ScriptedRandomizer::new(sequence))
+	}
+}
+
+impl Randomizer for ScriptedRandomizer {
+	fn next(&mut self) -> MinoShape {
+		let shape = self.sequence[self.i];
+		self.i = (self.i + 1) % self.sequence.len();
+
+		shape
+	}
+
+	fn probabilities(&self) -> [f64; 7] {
+		let mut probabilities = [0.0; 7];
+		probabilities[self.sequence[self.i].index()] = 1.0;
+
+		probabilities
+	}
+}
+
+#[cfg(test)]
+mod randomizer_tests {
+	use crate::*;
+
+	#[test]
+	fn nes_lfsr() {
+		let mut randomizer = NesRandomizer::new(NesRandomizer::POWER_ON_SEED);
+		randomizer.step();
+		assert_eq!(randomizer.seed(), 0x44C4);
+
+		// Maximal length for a 15-bit LFSR
+		let mut period = 1;
+		while randomizer.seed() != NesRandomizer::POWER_ON_SEED {
+			randomizer.step();
+			period += 1;
+		}
+		assert_eq!(period, 32767);
+	}
+
+	#[test]
+	fn nes_pieces() {
+		let mut randomizer = NesRandomizer::new(NesRandomizer::POWER_ON_SEED);
+		// 0x89 + 1 = 0x8A, index 2
+		assert_eq!(randomizer.next(), MinoShape::Z);
+		// 0x89 + 2 = 0x8B, index 3
+		assert_eq!(randomizer.next(), MinoShape::O);
+
+		// Repeats only happen on a reroll, so they're rarer than 1 in 7
+		let mut repeats = 0;
+		let mut last = randomizer.next();
+		for i in 0..7000 {
+			randomizer.elapse(20 + i % 13);
+			let next = randomizer.next();
+			if next == last {
+				repeats += 1;
+			}
+			last = next;
+		}
+		assert!(repeats < 500);
+	}
+
+	#[test]
+	fn bags() {
+		let mut randomizer = BagRandomizer::new(7);
+		for _ in 0..10 {
+			let bag: Vec<MinoShape> = (0..7).map(|_| randomizer.next()).collect();
+			for shape in MinoShape::ALL.iter() {
+				assert!(bag.contains(shape));
+			}
+		}
+	}
+
+	#[test]
+	fn history() {
+		let mut randomizer = HistoryRandomizer::tgm(7);
+		let first = randomizer.next();
+		assert!(first != MinoShape::S && first != MinoShape::Z && first != MinoShape::O);
+
+		// With enough rolls, nothing repeats within the history
+		let mut randomizer = HistoryRandomizer::new(7, [MinoShape::Z; 4], 1000);
+		let pieces: Vec<MinoShape> = (0..100).map(|_| randomizer.next()).collect();
+		for window in pieces.windows(5) {
+			assert!(!window[..4].contains(&window[4]));
+		}
+	}
+
+	#[test]
+	fn zero_state() {
+		// The seed that mixes to zero still produces pieces
+		let mut randomizer = UniformRandomizer::new(0x9E37_79B9_7F4A_7C15);
+		let pieces: Vec<MinoShape> = (0..20).map(|_| randomizer.next()).collect();
+		assert!(pieces.iter().any(|shape| *shape != pieces[0]));
+	}
+
+	#[test]
+	fn uniform() {
+		let mut randomizer = UniformRandomizer::new(7);
+		let mut counts = [0; 7];
+		for _ in 0..7000 {
+			counts[randomizer.next().index()] += 1;
+		}
+		assert!(counts.iter().all(|count| 800 < *count && *count < 1200));
+	}
+
+	#[test]
+	fn probabilities() {
+		let total = |probabilities: [f64; 7]| probabilities.iter().sum::<f64>();
+		let uniform = [1.0 / 7.0; 7];
+
+		// NES: before the first piece, only index 7 rerolls, and r = 0 and
+		// r = 7 both reroll into a T. After an O (spawn ID 0x0A), O can't
+		// come up on the first roll, but rerolls favor (r + 10) % 7 = 3, O
+		let mut randomizer = NesRandomizer::new(NesRandomizer::POWER_ON_SEED);
+		assert_eq!(randomizer.probabilities()[0], 1.0 / 8.0 + 1.0 / 8.0 * 2.0 / 8.0);
+		randomizer.next();
+		assert_eq!(randomizer.next(), MinoShape::O);
+		let probabilities = randomizer.probabilities();
+		assert!((total(probabilities) - 1.0).abs() < 1e-9);
+		assert_eq!(probabilities[3], 2.0 / 8.0 * 2.0 / 8.0);
+		assert_eq!(probabilities[0], 1.0 / 8.0 + 2.0 / 8.0 / 8.0);
+
+		// Bags: only what's left
+		let mut randomizer = BagRandomizer::new(7);
+		assert_eq!(randomizer.probabilities(), uniform);
+		let dealt: Vec<MinoShape> = (0..5).map(|_| randomizer.next()).collect();
+		let probabilities = randomizer.probabilities();
+		for (shape, probability) in MinoShape::ALL.iter().zip(probabilities.iter()) {
+			assert_eq!(*probability, if dealt.contains(shape) { 0.0 } else { 0.5 });
+		}
+
+		// History: 4 rolls against a history of 4 different pieces
+		let history = [MinoShape::T, MinoShape::J, MinoShape::Z, MinoShape::O];
+		let mut randomizer = HistoryRandomizer::new(7, history, 4);
+		assert_eq!(randomizer.probabilities()[0], 0.25);
+		randomizer.next();
+		randomizer.history = history;
+		let probabilities = randomizer.probabilities();
+		assert!((total(probabilities) - 1.0).abs() < 1e-9);
+		assert!(probabilities[0] < probabilities[4]);
+
+		assert_eq!(UniformRandomizer::new(7).probabilities(), uniform);
+		let randomizer = ScriptedRandomizer::new(vec![MinoShape::I, MinoShape::T]);
+		assert_eq!(randomizer.probabilities()[6], 1.0);
+	}
+
+	#[test]
+	fn scripted() {
+		let mut randomizer = ScriptedRandomizer::new(vec![MinoShape::I, MinoShape::T]);
+		assert_eq!(randomizer.next(), MinoShape::I);
+		assert_eq!(randomizer.next(), MinoShape::T);
+		assert_eq!(randomizer.next(), MinoShape::I);
+
+		let path = std::env::temp_dir().join("tetris_scripted_randomizer.txt");
+		std::fs::write(&path, "SZ O\nL").unwrap();
+		let mut randomizer = ScriptedRandomizer::from_file(&path).unwrap();
+		let pieces: Vec<MinoShape> = (0..4).map(|_| randomizer.next()).collect();
+		assert_eq!(pieces, vec![MinoShape::S, MinoShape::Z, MinoShape::O, MinoShape::L]);
+
+		std::fs::write(&path, "SZX").unwrap();
+		assert!(ScriptedRandomizer::from_file(&path).is_err());
+		std::fs::remove_file(&path).unwrap();
+	}
+}
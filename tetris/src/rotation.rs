@@ -0,0 +1,259 @@
+use crate::*;
+
+/// Cell offsets for every rotation of a shape, indexed by rotation.
+pub type Cells = [[(i32, i32); 4]];
+
+/// Rules for how minos spawn and rotate.
+pub trait RotationSystem {
+	/// Get the mino that `shape` spawns as.
+	fn spawn(&self, shape: MinoShape) -> Mino;
+
+	/// Get the offsets to try, in order, when rotating `mino` `n` times
+	/// clockwise (-1 for counterclockwise). The first one that fits is used.
+	fn kicks(&self, mino: Mino, n: i32) -> &'static [(i32, i32)];
+
+	/// Produce `mino` rotated `n` times clockwise on `board`, kicked into the
+	/// first position that fits. Gives `None` if none of them do.
+	fn rotate<const W: usize, const H: usize>(&self, board: &Board<W, H>, mino: Mino, n: i32) -> Option<Mino> {
+		let rotated = mino.rotated(n);
+
+		self.kicks(mino, n).iter()
+			.map(|(dx, dy)| rotated.translated(*dx, *dy))
+			.find(|kicked| board.can_place(*kicked))
+	}
+
+	/// Produce the mino that results from making move `m` with `mino` on
+	/// `board`, if it fits.
+	fn apply<const W: usize, const H: usize>(&self, board: &Board<W, H>, mino: Mino, m: Move) -> Option<Mino> {
+		match m {
+			Move::Clockwise => self.rotate(board, mino, 1),
+			Move::CounterClockwise => self.rotate(board, mino, -1),
+			_ => Some(m.apply(mino)).filter(|moved| board.can_place(*moved))
+		}
+	}
+}
+
+// Thanks to meatfighter.com for the NES tables
+static NES_T: [[(i32, i32); 4]; 4] = [
+	[(-1, 0), (0, 0), (1, 0), (0, -1)],
+	[(0, -1), (0, 0), (1, 0), (0, 1)],
+	[(-1, 0), (0, 0), (1, 0), (0, 1)],
+	[(0, -1), (-1, 0), (0, 0), (0, 1)]
+];
+static NES_J: [[(i32, i32); 4]; 4] = [
+	[(0, -1), (0, 0), (-1, 1), (0, 1)],
+	[(-1, -1), (-1, 0), (0, 0), (1, 0)],
+	[(0, -1), (1, -1), (0, 0), (0, 1)],
+	[(-1, 0), (0, 0), (1, 0), (1, 1)]
+];
+static NES_Z: [[(i32, i32); 4]; 2] = [
+	[(-1, 0), (0, 0), (0, 1), (1, 1)],
+	[(1, -1), (0, 0), (1, 0), (0, 1)]
+];
+static NES_O: [[(i32, i32); 4]; 1] = [
+	[(-1, 0), (0, 0), (-1, 1), (0, 1)]
+];
+static NES_S: [[(i32, i32); 4]; 2] = [
+	[(0, 0), (1, 0), (-1, 1), (0, 1)],
+	[(0, -1), (0, 0), (1, 0), (1, 1)]
+];
+static NES_L: [[(i32, i32); 4]; 4] = [
+	[(0, -1), (0, 0), (0, 1), (1, 1)],
+	[(-1, 0), (0, 0), (1, 0), (-1, 1)],
+	[(-1, -1), (0, -1), (0, 0), (0, 1)],
+	[(1, -1), (-1, 0), (0, 0), (1, 0)]
+];
+static NES_I: [[(i32, i32); 4]; 2] = [
+	[(0, -2), (0, -1), (0, 0), (0, 1)],
+	[(-2, 0), (-1, 0), (0, 0), (1, 0)]
+];
+
+/// Get the NES cell offsets for `shape`.
+pub(crate) fn nes_cells(shape: MinoShape) -> &'static Cells {
+	match shape {
+		MinoShape::T => &NES_T,
+		MinoShape::J => &NES_J,
+		MinoShape::Z => &NES_Z,
+		MinoShape::O => &NES_O,
+		MinoShape::S => &NES_S,
+		MinoShape::L => &NES_L,
+		MinoShape::I => &NES_I
+	}
+}
+
+/// Rotation in NES Tetris: minos turn in place with no kicks, and S, Z and I
+/// only have two orientations.
+#[derive(Clone, Copy, Debug)]
+pub struct NesRotation;
+
+impl RotationSystem for NesRotation {
+	fn spawn(&self, shape: MinoShape) -> Mino {
+		Mino::new(shape)
+	}
+
+	fn kicks(&self, _mino: Mino, _n: i32) -> &'static [(i32, i32)] {
+		&[(0, 0)]
+	}
+}
+
+// SRS tables, rotations in order spawn, right, 180, left
+static SRS_T: [[(i32, i32); 4]; 4] = [
+	[(0, -1), (-1, 0), (0, 0), (1, 0)],
+	[(0, -1), (0, 0), (1, 0), (0, 1)],
+	[(-1, 0), (0, 0), (1, 0), (0, 1)],
+	[(0, -1), (-1, 0), (0, 0), (0, 1)]
+];
+static SRS_J: [[(i32, i32); 4]; 4] = [
+	[(-1, -1), (-1, 0), (0, 0), (1, 0)],
+	[(0, -1), (1, -1), (0, 0), (0, 1)],
+	[(-1, 0), (0, 0), (1, 0), (1, 1)],
+	[(0, -1), (0, 0), (-1, 1), (0, 1)]
+];
+static SRS_Z: [[(i32, i32); 4]; 4] = [
+	[(-1, -1), (0, -1), (0, 0), (1, 0)],
+	[(1, -1), (0, 0), (1, 0), (0, 1)],
+	[(-1, 0), (0, 0), (0, 1), (1, 1)],
+	[(0, -1), (-1, 0), (0, 0), (-1, 1)]
+];
+static SRS_O: [[(i32, i32); 4]; 1] = [
+	[(0, -1), (1, -1), (0, 0), (1, 0)]
+];
+static SRS_S: [[(i32, i32); 4]; 4] = [
+	[(0, -1), (1, -1), (-1, 0), (0, 0)],
+	[(0, -1), (0, 0), (1, 0), (1, 1)],
+	[(0, 0), (1, 0), (-1, 1), (0, 1)],
+	[(-1, -1), (-1, 0), (0, 0), (0, 1)]
+];
+static SRS_L: [[(i32, i32); 4]; 4] = [
+	[(1, -1), (-1, 0), (0, 0), (1, 0)],
+	[(0, -1), (0, 0), (0, 1), (1, 1)],
+	[(-1, 0), (0, 0), (1, 0), (-1, 1)],
+	[(-1, -1), (0, -1), (0, 0), (0, 1)]
+];
+static SRS_I: [[(i32, i32); 4]; 4] = [
+	[(-1, 0), (0, 0), (1, 0), (2, 0)],
+	[(1, -1), (1, 0), (1, 1), (1, 2)],
+	[(-1, 1), (0, 1), (1, 1), (2, 1)],
+	[(0, -1), (0, 0), (0, 1), (0, 2)]
+];
+
+// SRS kicks, indexed by the rotation being turned from, for clockwise turns.
+// Counterclockwise kicks from `r` are the negated clockwise kicks into `r`.
+// Positive y is down, so these are upside down compared to most references.
+static SRS_KICKS_CW: [[(i32, i32); 5]; 4] = [
+	[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+	[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+	[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+	[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]
+];
+static SRS_KICKS_CCW: [[(i32, i32); 5]; 4] = [
+	[(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+	[(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+	[(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+	[(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]
+];
+static SRS_I_KICKS_CW: [[(i32, i32); 5]; 4] = [
+	[(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)],
+	[(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)],
+	[(0, 0), (2, 0), (-1, 0), (2, -1), (-1, 2)],
+	[(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)]
+];
+static SRS_I_KICKS_CCW: [[(i32, i32); 5]; 4] = [
+	[(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)],
+	[(0, 0), (2, 0), (-1, 0), (2, -1), (-1, 2)],
+	[(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)],
+	[(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)]
+];
+
+/// The Super Rotation System from modern guideline Tetris: every mino but O
+/// has four orientations, and a blocked rotation tries up to four kicks
+/// before giving up. Minos spawn flat side down, centered (rounding left) in
+/// the top two rows.
+#[derive(Clone, Copy, Debug)]
+pub struct SrsRotation;
+
+impl SrsRotation {
+	pub(crate) fn cells(shape: MinoShape) -> &'static Cells {
+		match shape {
+			MinoShape::T => &SRS_T,
+			MinoShape::J => &SRS_J,
+			MinoShape::Z => &SRS_Z,
+			MinoShape::O => &SRS_O,
+			MinoShape::S => &SRS_S,
+			MinoShape::L => &SRS_L,
+			MinoShape::I => &SRS_I
+		}
+	}
+}
+
+impl RotationSystem for SrsRotation {
+	fn spawn(&self, shape: MinoShape) -> Mino {
+		Mino::with_cells(shape, SrsRotation::cells(shape), 0, 4, 0)
+	}
+
+	fn kicks(&self, mino: Mino, n: i32) -> &'static [(i32, i32)] {
+		let from = mino.rot() as usize;
+		match (mino.shape(), n.rem_euclid(4)) {
+			(MinoShape::O, _) | (_, 0) => &[(0, 0)],
+			(MinoShape::I, 1) => &SRS_I_KICKS_CW[from],
+			(MinoShape::I, 3) => &SRS_I_KICKS_CCW[from],
+			(_, 1) => &SRS_KICKS_CW[from],
+			(_, 3) => &SRS_KICKS_CCW[from],
+			// No 180 spins
+			_ => &[]
+		}
+	}
+}
+
+#[cfg(test)]
+mod rotation_tests {
+	use crate::*;
+
+	#[test]
+	fn srs_spawn() {
+		let mino = SrsRotation.spawn(MinoShape::T);
+		assert_eq!(mino.points(), [(4, -1), (3, 0), (4, 0), (5, 0)]);
+
+		let mino = SrsRotation.spawn(MinoShape::I);
+		assert_eq!(mino.points(), [(3, 0), (4, 0), (5, 0), (6, 0)]);
+
+		let mino = SrsRotation.spawn(MinoShape::O);
+		assert_eq!(mino.points(), [(4, -1), (5, -1), (4, 0), (5, 0)]);
+	}
+
+	#[test]
+	fn srs_kicks() {
+		let board: Board = Board::blank();
+
+		// T pointing right against the left wall gets kicked out when it
+		// turns to point down
+		let mino = SrsRotation.spawn(MinoShape::T).rotated(1).translated(-4, 5);
+		assert_eq!(mino.x(), 0);
+		let rotated = SrsRotation.rotate(&board, mino, 1).unwrap();
+		assert_eq!((rotated.x(), rotated.y(), rotated.rot()), (1, 5, 2));
+
+		// Vertical I against the right wall kicks left
+		let mino = SrsRotation.spawn(MinoShape::I).rotated(1).translated(4, 5);
+		assert_eq!(mino.points()[0].0, 9);
+		let rotated = SrsRotation.rotate(&board, mino, 1).unwrap();
+		assert_eq!(rotated.points(), [(6, 6), (7, 6), (8, 6), (9, 6)]);
+
+		// NES doesn't kick at all
+		let mino = Mino::new(MinoShape::I).rotated(1).translated(4, 5);
+		assert!(NesRotation.rotate(&board, mino, 1).is_none());
+	}
+
+	#[test]
+	fn srs_possibilities() {
+		let state = State::new();
+		assert_eq!(state.possibilities(MinoShape::T, &SrsRotation).len(), 34);
+		// I, S and Z have four orientations, but they only cover two sets of
+		// cells, and O only covers one
+		assert_eq!(state.possibilities(MinoShape::I, &SrsRotation).len(), 17);
+		assert_eq!(state.placements(MinoShape::I, &SrsRotation, &NesScoring).len(), 17);
+		assert_eq!(state.possibilities(MinoShape::S, &SrsRotation).len(), 17);
+		assert_eq!(state.possibilities(MinoShape::Z, &SrsRotation).len(), 17);
+		assert_eq!(state.possibilities(MinoShape::O, &SrsRotation).len(), 9);
+		assert_eq!(state.possibilities(MinoShape::I, &NesRotation).len(), 17);
+	}
+}
@@ -0,0 +1,179 @@
+use crate::*;
+use std::cmp::{max, min};
+
+/// Highest score the NES can show; it stops counting there.
+pub const MAX_SCORE: i32 = 999999;
+
+/// Whether a placement was a T-spin, and what kind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TSpin {
+	None,
+	Mini,
+	Full
+}
+
+/// Everything about a single placement that scoring rules care about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Clear {
+	/// Number of lines cleared.
+	pub lines: i32,
+	/// Number of rows the piece was soft dropped (pushed down) before locking.
+	pub push_down: i32,
+	/// Whether the placement was a T-spin.
+	pub t_spin: TSpin,
+	/// Whether the board was left empty.
+	pub perfect: bool
+}
+
+impl Clear {
+	/// Check whether this clear counts towards back-to-back bonuses, i.e. a
+	/// Tetris or a T-spin that clears lines.
+	pub fn is_difficult(&self) -> bool {
+		self.lines == 4 || (self.lines > 0 && self.t_spin != TSpin::None)
+	}
+}
+
+/// A way of turning placements into points.
+pub trait ScoringRule {
+	/// Get the score after `clear` happens in `state`. `state` is from before
+	/// the placement, so its level, combo and back-to-back status are the ones
+	/// the placement was made under.
+	fn score<const W: usize, const H: usize>(&self, state: &State<W, H>, clear: &Clear) -> i32;
+}
+
+/// Scoring in NES Tetris: line clears are worth 40/100/300/1200 times one
+/// more than the level, plus push-down points, and the score stops at
+/// `MAX_SCORE`. There are no bonuses for T-spins, combos or perfect clears.
+#[derive(Clone, Copy, Debug)]
+pub struct NesScoring;
+
+impl ScoringRule for NesScoring {
+	fn score<const W: usize, const H: usize>(&self, state: &State<W, H>, clear: &Clear) -> i32 {
+		// Holding down counts each row pushed, plus the push that locks
+		let held = if clear.push_down > 0 { clear.push_down + 1 } else { 0 };
+
+		let score = add_push_down_points(state.score, held) + match clear.lines {
+			0 => 0,
+			1 => 40*(state.level+1),
+			2 => 100*(state.level+1),
+			3 => 300*(state.level+1),
+			4 => 1200*(state.level+1),
+			n => panic!("Cleared too many ({}) lines in one turn", n)
+		};
+
+		min(score, MAX_SCORE)
+	}
+}
+
+/// Add push-down points to `score` the way NES Tetris does, given `held`, the
+/// number of frames the piece moved (or tried to move) down while Down was
+/// held.
+///
+/// The cartridge awards `held - 1` points, but adds them in binary to the
+/// BCD-encoded last two digits of the score and then patches up the digits.
+/// The patching isn't quite right, so some scores come out a little off - for
+/// example, when the tens digit carries, the ones digit is lost.
+fn add_push_down_points(score: i32, held: i32) -> i32 {
+	if held < 2 {
+		return score;
+	}
+
+	let low = score % 100;
+	let mut bcd = (((low / 10) << 4) | (low % 10)) as u8;
+
+	bcd = bcd.wrapping_sub(1).wrapping_add(held as u8);
+	if bcd & 0x0F >= 0x0A {
+		bcd = bcd.wrapping_add(0x06);
+	}
+
+	let mut carry = 0;
+	if bcd & 0xF0 >= 0xA0 {
+		bcd = (bcd & 0xF0).wrapping_add(0x60);
+		carry = 100;
+	}
+
+	score - low + ((bcd >> 4) as i32)*10 + (bcd & 0x0F) as i32 + carry
+}
+
+/// Scoring in modern guideline Tetris: line clears and T-spins are worth more
+/// when done back to back, consecutive clears build a combo, perfect clears
+/// earn a bonus, and soft drops are worth a point per row. Everything but the
+/// soft drop points is multiplied by the level, counting from 1.
+#[derive(Clone, Copy, Debug)]
+pub struct GuidelineScoring;
+
+impl ScoringRule for GuidelineScoring {
+	fn score<const W: usize, const H: usize>(&self, state: &State<W, H>, clear: &Clear) -> i32 {
+		let level = max(state.level, 1);
+
+		let base = match (clear.t_spin, clear.lines) {
+			(TSpin::None, 0) => 0,
+			(TSpin::None, 1) => 100,
+			(TSpin::None, 2) => 300,
+			(TSpin::None, 3) => 500,
+			(TSpin::None, 4) => 800,
+			(TSpin::Mini, 0) => 100,
+			(TSpin::Mini, 1) => 200,
+			(TSpin::Mini, 2) => 400,
+			(TSpin::Full, 0) => 400,
+			(TSpin::Full, 1) => 800,
+			(TSpin::Full, 2) => 1200,
+			(TSpin::Full, 3) => 1600,
+			(t_spin, n) => panic!("Impossible clear of {} lines with T-spin {:?}", n, t_spin)
+		};
+		let back_to_back = state.back_to_back && clear.is_difficult();
+		let base = if back_to_back { base * 3/2 } else { base };
+
+		let combo = if clear.lines > 0 { 50 * state.combo } else { 0 };
+
+		let perfect = match (clear.perfect, clear.lines) {
+			(false, _) => 0,
+			(true, 1) => 800,
+			(true, 2) => 1200,
+			(true, 3) => 1800,
+			(true, _) if back_to_back => 3200,
+			(true, _) => 2000
+		};
+
+		state.score + (base + combo + perfect)*level + clear.push_down
+	}
+}
+
+#[cfg(test)]
+mod scoring_tests {
+	use crate::*;
+
+	fn clear(lines: i32, t_spin: TSpin) -> Clear {
+		Clear {
+			lines,
+			push_down: 0,
+			t_spin,
+			perfect: false
+		}
+	}
+
+	#[test]
+	fn guideline() {
+		let mut state = State::with_start(1);
+
+		// Plain Tetris, then a back-to-back T-spin double
+		state.score = GuidelineScoring.score(&state, &clear(4, TSpin::None));
+		assert_eq!(state.score, 800);
+		state.back_to_back = true;
+		state.combo = 1;
+		state.score = GuidelineScoring.score(&state, &clear(2, TSpin::Full));
+		assert_eq!(state.score, 800 + 1800 + 50);
+
+		// A single breaks back-to-back without being penalized itself
+		state.combo = 2;
+		state.score = GuidelineScoring.score(&state, &clear(1, TSpin::None));
+		assert_eq!(state.score, 2650 + 100 + 100);
+
+		// Perfect clear Tetris at level 2
+		let mut state = State::with_start(2);
+		let perfect = Clear { perfect: true, ..clear(4, TSpin::None) };
+		assert_eq!(GuidelineScoring.score(&state, &perfect), (800 + 2000)*2);
+		state.back_to_back = true;
+		assert_eq!(GuidelineScoring.score(&state, &perfect), (1200 + 3200)*2);
+	}
+}
@@ -0,0 +1,295 @@
+use crate::*;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// Text format, shared with the decision server protocol: one line per row of
+// the board, '.' for empty and 'x' for filled, then a line with the current
+// and next pieces (for snapshots only) and a line with level, score and lines.
+//
+// ..........
+// ...
+// xxxxxxxxx.
+// Z I
+// 18 22800 4
+//
+// The text doesn't say what level the game started at, so parsing works it
+// out from the level and lines (see `start_level`). Combo, back-to-back and
+// hold aren't written either, so they're lost in a round trip.
+
+/// Reasons that text can fail to parse as a board, state or snapshot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseError {
+	/// The text ended before line `line` (counting from 0).
+	MissingLine(usize),
+	/// Row `y` of the board isn't as wide as the board.
+	WrongWidth { y: usize, width: usize },
+	/// Cell (x, y) is neither '.' nor 'x'.
+	BadCell { x: usize, y: usize, c: char },
+	/// A piece isn't one of the seven shape letters.
+	BadPiece(String),
+	/// Level, score or lines isn't an integer.
+	BadNumber(String),
+	/// There's more text after everything expected.
+	TrailingText(String)
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ParseError::MissingLine(line) => write!(f, "Missing line {}", line),
+			ParseError::WrongWidth { y, width } => write!(f, "Row {} is {} cells wide", y, width),
+			ParseError::BadCell { x, y, c } => write!(f, "Unparseable character {} at ({}, {})", c, x, y),
+			ParseError::BadPiece(piece) => write!(f, "Unparseable mino type {}", piece),
+			ParseError::BadNumber(number) => write!(f, "Unparseable number {}", number),
+			ParseError::TrailingText(text) => write!(f, "Unexpected text {}", text)
+		}
+	}
+}
+
+impl Error for ParseError {}
+
+/// A state along with the pieces about to be played - everything a bot needs
+/// to decide on a move.
+#[derive(Clone, Copy, Debug)]
+pub struct Snapshot<const W: usize = 10, const H: usize = 20> {
+	pub state: State<W, H>,
+	pub current: MinoShape,
+	pub next: Option<MinoShape>
+}
+
+impl<const W: usize, const H: usize> fmt::Display for Board<W, H> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for row in self.grid().iter() {
+			for cell in row.iter() {
+				write!(f, "{}", if *cell { 'x' } else { '.' })?;
+			}
+			writeln!(f)?;
+		}
+
+		Ok(())
+	}
+}
+
+impl<const W: usize, const H: usize> fmt::Display for State<W, H> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.board)?;
+		writeln!(f, "{} {} {}", self.level, self.score, self.lines)
+	}
+}
+
+impl<const W: usize, const H: usize> fmt::Display for Snapshot<W, H> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.state.board)?;
+		match self.next {
+			Some(next) => writeln!(f, "{:?} {:?}", self.current, next)?,
+			None => writeln!(f, "{:?}", self.current)?
+		}
+		writeln!(f, "{} {} {}", self.state.level, self.state.score, self.state.lines)
+	}
+}
+
+impl<const W: usize, const H: usize> FromStr for Board<W, H> {
+	type Err = ParseError;
+
+	fn from_str(s: &str) -> Result<Board<W, H>, ParseError> {
+		let mut lines = s.lines();
+		let board = parse_board(&mut lines)?;
+		no_more(&mut lines)?;
+
+		Ok(board)
+	}
+}
+
+impl<const W: usize, const H: usize> FromStr for State<W, H> {
+	type Err = ParseError;
+
+	/// Parse a state, working out the level it started at (see `start_level`).
+	fn from_str(s: &str) -> Result<State<W, H>, ParseError> {
+		let mut lines = s.lines();
+		let board = parse_board(&mut lines)?;
+		let state = parse_numbers(&mut lines, board, H)?;
+		no_more(&mut lines)?;
+
+		Ok(state)
+	}
+}
+
+impl<const W: usize, const H: usize> FromStr for Snapshot<W, H> {
+	type Err = ParseError;
+
+	fn from_str(s: &str) -> Result<Snapshot<W, H>, ParseError> {
+		let mut lines = s.lines();
+		let board = parse_board(&mut lines)?;
+
+		let pieces = lines.next().ok_or(ParseError::MissingLine(H))?;
+		let pieces: Vec<&str> = pieces.split_whitespace().collect();
+		let (current, next) = match pieces[..] {
+			[] => { return Err(ParseError::BadPiece(String::new())); },
+			[current] => (parse_piece(current)?, None),
+			[current, next] => (parse_piece(current)?, Some(parse_piece(next)?)),
+			[_, _, extra, ..] => { return Err(ParseError::TrailingText(extra.to_string())); }
+		};
+
+		let state = parse_numbers(&mut lines, board, H + 1)?;
+		no_more(&mut lines)?;
+
+		Ok(Snapshot {
+			state,
+			current,
+			next
+		})
+	}
+}
+
+/// Parse the first lines of `lines` as the rows of a board.
+fn parse_board<'a, I, const W: usize, const H: usize>(lines: &mut I) -> Result<Board<W, H>, ParseError>
+	where I: Iterator<Item = &'a str>
+{
+	let mut board = Board::blank();
+	for y in 0..H {
+		let line = lines.next().ok_or(ParseError::MissingLine(y))?;
+
+		let width = line.chars().count();
+		if width != W {
+			return Err(ParseError::WrongWidth { y, width });
+		}
+
+		for (x, c) in line.chars().enumerate() {
+			match c {
+				'.' => {},
+				'x' => { board.set(x, y, true); },
+				_ => { return Err(ParseError::BadCell { x, y, c }); }
+			}
+		}
+	}
+
+	Ok(board)
+}
+
+fn parse_piece(piece: &str) -> Result<MinoShape, ParseError> {
+	let mut chars = piece.chars();
+	match (chars.next().and_then(MinoShape::from_char), chars.next()) {
+		(Some(shape), None) => Ok(shape),
+		_ => Err(ParseError::BadPiece(piece.to_string()))
+	}
+}
+
+/// Parse the level, score and lines line (line number `line`) into a state
+/// with `board`.
+fn parse_numbers<'a, I, const W: usize, const H: usize>(lines: &mut I, board: Board<W, H>, line: usize) -> Result<State<W, H>, ParseError>
+	where I: Iterator<Item = &'a str>
+{
+	let text = lines.next().ok_or(ParseError::MissingLine(line))?;
+
+	let mut numbers = [0; 3];
+	let mut words = text.split_whitespace();
+	for number in numbers.iter_mut() {
+		let word = words.next().ok_or_else(|| ParseError::BadNumber(text.to_string()))?;
+		*number = word.parse().map_err(|_| ParseError::BadNumber(word.to_string()))?;
+	}
+	if let Some(word) = words.next() {
+		return Err(ParseError::TrailingText(word.to_string()));
+	}
+
+	let [level, score, lines] = numbers;
+	let mut state = State::with_board(board, level);
+	state.score = score;
+	state.lines = lines;
+	state.start = start_level(level, lines);
+
+	Ok(state)
+}
+
+/// Work out a start level that has a game at `level` after clearing `lines`
+/// lines, so that it keeps leveling up on schedule.
+///
+/// Past the first transition, different starts can leave a game in the same
+/// place (any start up to 9 is at level 20 after 200 lines), and then the
+/// highest one is as good as any. If no start fits, `level` is used.
+fn start_level(level: i32, lines: i32) -> i32 {
+	(0..=level).rev()
+		.find(|start| level_after(*start, lines) == level)
+		.unwrap_or(level)
+}
+
+/// Check that only blank lines are left.
+fn no_more<'a, I: Iterator<Item = &'a str>>(lines: &mut I) -> Result<(), ParseError> {
+	match lines.find(|line| !line.trim().is_empty()) {
+		Some(line) => Err(ParseError::TrailingText(line.to_string())),
+		None => Ok(())
+	}
+}
+
+#[cfg(test)]
+mod text_tests {
+	use crate::*;
+
+	const REQUEST: &str = "\
+..........
+..........
+..........
+..........
+..........
+..........
+..........
+..........
+..........
+..........
+..........
+..........
+..........
+x.........
+xxx...xxx.
+xxxxxxxxx.
+xxxxxxxxx.
+xxxxxxxxx.
+xxxxxxxxx.
+xxxxxxxxx.
+Z I
+18 22800 4
+";
+
+	#[test]
+	fn round_trip() {
+		let snapshot: Snapshot = REQUEST.parse().unwrap();
+		assert_eq!((snapshot.current, snapshot.next), (MinoShape::Z, Some(MinoShape::I)));
+		assert_eq!((snapshot.state.level, snapshot.state.score, snapshot.state.lines), (18, 22800, 4));
+		assert_eq!(snapshot.state.column_depth(0), 13);
+		assert_eq!(snapshot.state.column_depth(9), 20);
+		assert_eq!(snapshot.to_string(), REQUEST);
+
+		// States are the same without the pieces line
+		let text = snapshot.state.to_string();
+		assert_eq!(text.lines().count(), 21);
+		let state: State = text.parse().unwrap();
+		assert_eq!(state.board.to_string(), snapshot.state.board.to_string());
+
+		// Start level isn't written, but it's worked out again
+		assert_eq!(state.start, 18);
+		let state: State = text.replace("18 22800 4", "20 22800 200").parse().unwrap();
+		assert_eq!(state.start, 9);
+		assert_eq!(level_after(state.start, 201), 20);
+		assert_eq!(level_after(state.start, 210), 21);
+		let state: State = text.replace("18 22800 4", "19 22800 135").parse().unwrap();
+		assert_eq!(state.start, 19);
+
+		let board: Board<4, 2> = "x..x\n.xx.\n".parse().unwrap();
+		assert_eq!((board.row_mask(0), board.row_mask(1)), (0b1001, 0b0110));
+		assert_eq!(board.to_string(), "x..x\n.xx.\n");
+	}
+
+	#[test]
+	fn errors() {
+		let parse = |text: &str| text.parse::<Snapshot>().unwrap_err();
+
+		assert_eq!(parse(&REQUEST.replace("Z I", "Z Q")), ParseError::BadPiece("Q".to_string()));
+		assert_eq!(parse(&REQUEST.replace("x.........", "x..y......")), ParseError::BadCell { x: 3, y: 13, c: 'y' });
+		assert_eq!(parse(&REQUEST.replace("x.........", "x........")), ParseError::WrongWidth { y: 13, width: 9 });
+		assert_eq!(parse(&REQUEST.replace("22800", "lots")), ParseError::BadNumber("lots".to_string()));
+		assert_eq!(parse(&REQUEST.replace("18 22800 4\n", "")), ParseError::MissingLine(21));
+		assert_eq!(parse(&format!("{}more", REQUEST)), ParseError::TrailingText("more".to_string()));
+
+		assert!("x..x\n.xx.\n".parse::<Board>().is_err());
+	}
+}
@@ -1,15 +1,5 @@
 use tetris::*;
 
-#[allow(dead_code)]
-fn print_board(state: &State) {
-	for row in state.board.grid.iter() {
-		for cell in row.iter() {
-			print!("{} ", if *cell { 'X' } else { '.' });
-		}
-		println!();
-	}
-}
-
 #[test]
 fn drop_one() {
 	let state = State::new();
@@ -79,4 +69,27 @@ fn full_clear() {
 	assert_eq!(state.score, 1200);
 	assert_eq!(state.level, 0);
 	assert_eq!(state.lines, 4);
-}
\ No newline at end of file
+}
+
+// Every randomizer can drive a game: stack pieces in the first placement
+// found until the board fills up
+#[test]
+fn randomizer_games() {
+	let randomizers: Vec<Box<dyn Randomizer>> = vec![
+		Box::new(NesRandomizer::new(NesRandomizer::POWER_ON_SEED)),
+		Box::new(UniformRandomizer::new(1)),
+		Box::new(BagRandomizer::new(1)),
+		Box::new(HistoryRandomizer::tgm(1)),
+		Box::new(ScriptedRandomizer::new(vec![MinoShape::O, MinoShape::I]))
+	];
+
+	for mut randomizer in randomizers.into_iter() {
+		let mut state = State::new();
+		let mut pieces = 0;
+		while let Some((next, _)) = state.possibilities(randomizer.next(), &NesRotation).into_iter().next() {
+			state = next;
+			pieces += 1;
+		}
+		assert!(pieces > 0);
+	}
+}